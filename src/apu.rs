@@ -1,2 +1,684 @@
-#[derive(Clone, Default)]
-pub(crate) struct APU {}
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
+
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+pub(crate) const CPU_CLOCK_NTSC: u32 = 1_789_773;
+
+pub(crate) const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// What to do with new samples when the buffer is full, because the frontend isn't draining it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AudioOverflowPolicy {
+    #[default]
+    DropOldest,
+    DropNewest,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AudioStats {
+    pub produced: u64,
+    pub dropped: u64,
+}
+
+// https://www.nesdev.org/wiki/APU_Length_Counter
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, //
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+// CPU cycles after the $4017 write at which each step of the sequence is clocked
+const FOUR_STEP_SEQUENCE: [u32; 4] = [7457, 14913, 22371, 29829];
+const FIVE_STEP_SEQUENCE: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+
+// https://www.nesdev.org/wiki/APU_DMC
+// NTSC timer periods in CPU cycles, selected by $4010
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+// only the length counter of each channel is modeled, which is enough for $4015
+#[derive(Clone, Copy, Default)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[index as usize >> 3];
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+}
+
+// the delta modulation channel plays 1 bit samples, which it reads from CPU memory
+// one byte at a time with DMA
+#[derive(Clone, Copy)]
+struct DeltaModulation {
+    irq_enabled: bool,
+    loop_flag: bool,
+    period: u16,
+    timer: u16,
+    output_level: u8,
+    // $4012 and $4013, where the sample starts over
+    sample_address: u16,
+    sample_length: u16,
+    // memory reader
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for DeltaModulation {
+    fn default() -> Self {
+        DeltaModulation {
+            irq_enabled: false,
+            loop_flag: false,
+            period: DMC_RATE_TABLE[0],
+            timer: DMC_RATE_TABLE[0],
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+}
+
+impl DeltaModulation {
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn clock(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            // start the next output cycle, which empties the buffer for the reader to refill
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.shift_register = sample;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct APU {
+    // pulse 1, pulse 2, triangle, noise
+    length_counters: [LengthCounter; 4],
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    // CPU cycles until a $4017 write restarts the sequence
+    frame_reset_delay: u8,
+    // an APU cycle is two CPU cycles long, this is the second half
+    odd_cycle: bool,
+    // cleared by reading $4015, which only has a shared reference to the bus
+    pub(crate) frame_irq: Cell<bool>,
+    dmc: DeltaModulation,
+    // unlike the frame IRQ, only acknowledged by writes to $4010 and $4015
+    dmc_irq: bool,
+
+    sample_rate: u32,
+    // fractional sample position, in units of 1 / CPU_CLOCK_NTSC samples
+    sample_clock: u32,
+    // one second of samples is buffered
+    samples: VecDeque<i16>,
+    overflow_policy: AudioOverflowPolicy,
+    stats: AudioStats,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self {
+            length_counters: Default::default(),
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_reset_delay: 0,
+            odd_cycle: false,
+            frame_irq: Cell::new(false),
+            dmc: DeltaModulation::default(),
+            dmc_irq: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0,
+            samples: VecDeque::with_capacity(DEFAULT_SAMPLE_RATE as usize),
+            overflow_policy: AudioOverflowPolicy::default(),
+            stats: AudioStats::default(),
+        }
+    }
+}
+
+impl APU {
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+        self.samples.clear();
+    }
+
+    // back to the power-on state for another cartridge, keeping the output settings
+    pub(crate) fn power_on(&mut self) {
+        *self = APU {
+            sample_rate: self.sample_rate,
+            overflow_policy: self.overflow_policy,
+            ..APU::default()
+        };
+    }
+
+    pub(crate) fn set_overflow_policy(&mut self, policy: AudioOverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    pub(crate) fn stats(&self) -> AudioStats {
+        self.stats
+    }
+
+    // the sample buffer is output rather than state, and isn't saved
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        for length_counter in &self.length_counters {
+            state.write_bool(length_counter.enabled);
+            state.write_bool(length_counter.halt);
+            state.write_u8(length_counter.counter);
+        }
+        state.write_u32(self.frame_cycle);
+        state.write_bool(self.five_step_mode);
+        state.write_bool(self.irq_inhibit);
+        state.write_u8(self.frame_reset_delay);
+        state.write_bool(self.odd_cycle);
+        state.write_bool(self.frame_irq.get());
+
+        state.write_bool(self.dmc.irq_enabled);
+        state.write_bool(self.dmc.loop_flag);
+        state.write_u16(self.dmc.period);
+        state.write_u16(self.dmc.timer);
+        state.write_u8(self.dmc.output_level);
+        state.write_u16(self.dmc.sample_address);
+        state.write_u16(self.dmc.sample_length);
+        state.write_u16(self.dmc.current_address);
+        state.write_u16(self.dmc.bytes_remaining);
+        state.write_bool(self.dmc.sample_buffer.is_some());
+        state.write_u8(self.dmc.sample_buffer.unwrap_or(0));
+        state.write_u8(self.dmc.shift_register);
+        state.write_u8(self.dmc.bits_remaining);
+        state.write_bool(self.dmc.silence);
+        state.write_bool(self.dmc_irq);
+
+        state.write_u32(self.sample_clock);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        for length_counter in &mut self.length_counters {
+            length_counter.enabled = state.read_bool()?;
+            length_counter.halt = state.read_bool()?;
+            length_counter.counter = state.read_u8()?;
+        }
+        self.frame_cycle = state.read_u32()?;
+        self.five_step_mode = state.read_bool()?;
+        self.irq_inhibit = state.read_bool()?;
+        self.frame_reset_delay = state.read_u8()?;
+        self.odd_cycle = state.read_bool()?;
+        self.frame_irq.set(state.read_bool()?);
+
+        self.dmc.irq_enabled = state.read_bool()?;
+        self.dmc.loop_flag = state.read_bool()?;
+        self.dmc.period = state.read_u16()?;
+        self.dmc.timer = state.read_u16()?;
+        self.dmc.output_level = state.read_u8()?;
+        self.dmc.sample_address = state.read_u16()?;
+        self.dmc.sample_length = state.read_u16()?;
+        self.dmc.current_address = state.read_u16()?;
+        self.dmc.bytes_remaining = state.read_u16()?;
+        let has_sample = state.read_bool()?;
+        let sample = state.read_u8()?;
+        self.dmc.sample_buffer = has_sample.then_some(sample);
+        self.dmc.shift_register = state.read_u8()?;
+        self.dmc.bits_remaining = state.read_u8()?;
+        self.dmc.silence = state.read_bool()?;
+        self.dmc_irq = state.read_bool()?;
+
+        self.sample_clock = state.read_u32()? % CPU_CLOCK_NTSC;
+
+        if self.frame_cycle > FIVE_STEP_SEQUENCE[4]
+            || self.frame_reset_delay > 4
+            || !DMC_RATE_TABLE.contains(&self.dmc.period)
+            || self.dmc.timer > self.dmc.period
+            || self.dmc.output_level > 0x7f
+            || self.dmc.current_address < 0x8000
+            || !(1..=8).contains(&self.dmc.bits_remaining)
+        {
+            return Err(LoadStateError::Corrupt);
+        }
+
+        Ok(())
+    }
+
+    // https://www.nesdev.org/wiki/APU_registers
+    pub(crate) fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x4000 | 0x4004 | 0x400c => {
+                self.length_counters[(address as usize - 0x4000) / 4].halt = data & 0x20 != 0
+            }
+            0x4008 => self.length_counters[2].halt = data & 0x80 != 0,
+            0x4003 | 0x4007 | 0x400b | 0x400f => {
+                self.length_counters[(address as usize - 0x4000) / 4].load(data)
+            }
+            0x4010 => {
+                self.dmc.irq_enabled = data & 0x80 != 0;
+                self.dmc.loop_flag = data & 0x40 != 0;
+                self.dmc.period = DMC_RATE_TABLE[data as usize & 0x0f];
+                if !self.dmc.irq_enabled {
+                    self.dmc_irq = false;
+                }
+            }
+            0x4011 => self.dmc.output_level = data & 0x7f,
+            0x4012 => self.dmc.sample_address = 0xc000 | (data as u16) << 6,
+            0x4013 => self.dmc.sample_length = (data as u16) << 4 | 1,
+            0x4015 => {
+                for (channel, length_counter) in self.length_counters.iter_mut().enumerate() {
+                    length_counter.set_enabled(data & (1 << channel) != 0);
+                }
+
+                // the sample only restarts once the last one has finished
+                if data & 0x10 == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+                self.dmc_irq = false;
+            }
+            0x4017 => {
+                self.five_step_mode = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq.set(false);
+                }
+
+                // https://www.nesdev.org/wiki/APU_Frame_Counter
+                // the sequence restarts 3 CPU cycles after a write during an APU cycle, 4 after
+                // one between them. 5-step mode clocks the units right away
+                self.frame_reset_delay = if self.odd_cycle { 4 } else { 3 };
+                if self.five_step_mode {
+                    self.clock_half_frame();
+                }
+            }
+            // envelopes, sweeps and the other timers aren't modeled yet
+            _ => {}
+        }
+    }
+
+    // $4015 read: length counter and DMC status, and the interrupts.
+    // the frame interrupt is acknowledged by the read
+    pub(crate) fn read_status(&self) -> u8 {
+        let status =
+            self.length_counters
+                .iter()
+                .enumerate()
+                .fold(0, |status, (channel, length_counter)| {
+                    status | ((length_counter.counter > 0) as u8) << channel
+                })
+                | ((self.dmc.bytes_remaining > 0) as u8) << 4
+                | (self.frame_irq.get() as u8) << 6
+                | (self.dmc_irq as u8) << 7;
+
+        self.frame_irq.set(false);
+        status
+    }
+
+    pub(crate) fn read_irq_line(&self) -> bool {
+        self.frame_irq.get() || self.dmc_irq
+    }
+
+    // https://www.nesdev.org/wiki/APU_DMC#Memory_reader
+    // the address the DMC wants to read, when its sample buffer is empty and the sample isn't over
+    pub(crate) fn dmc_dma_address(&self) -> Option<u16> {
+        match (self.dmc.sample_buffer, self.dmc.bytes_remaining) {
+            (None, 1..) => Some(self.dmc.current_address),
+            _ => None,
+        }
+    }
+
+    // APU cycles until dmc_dma_address asks for the next byte, counting the cycle that empties
+    // the buffer, or None when the sample is over
+    pub(crate) fn cycles_until_dmc_dma(&self) -> Option<u64> {
+        let dmc = &self.dmc;
+        match (dmc.sample_buffer, dmc.bytes_remaining) {
+            (_, 0) => None,
+            (None, _) => Some(1),
+            // the buffer is emptied when the output unit starts its next cycle
+            (Some(_), _) => {
+                Some(dmc.timer as u64 + (dmc.bits_remaining as u64 - 1) * dmc.period as u64)
+            }
+        }
+    }
+
+    // hand the DMC the byte it asked for with dmc_dma_address
+    pub(crate) fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.sample_buffer = Some(data);
+
+        // the address wraps around to $8000, not $0000
+        self.dmc.current_address = self.dmc.current_address.checked_add(1).unwrap_or(0x8000);
+        self.dmc.bytes_remaining -= 1;
+
+        if self.dmc.bytes_remaining == 0 {
+            if self.dmc.loop_flag {
+                self.dmc.restart();
+            } else if self.dmc.irq_enabled {
+                self.dmc_irq = true;
+            }
+        }
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.length_counters
+            .iter_mut()
+            .for_each(|length_counter| length_counter.clock());
+    }
+
+    fn step_frame_counter(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+            }
+        }
+        self.frame_cycle += 1;
+
+        // the quarter frame steps only clock envelopes and the linear counter, which aren't modeled
+        let (half_frames, period) = if self.five_step_mode {
+            (
+                [FIVE_STEP_SEQUENCE[1], FIVE_STEP_SEQUENCE[4]],
+                FIVE_STEP_SEQUENCE[4] + 1,
+            )
+        } else {
+            (
+                [FOUR_STEP_SEQUENCE[1], FOUR_STEP_SEQUENCE[3]],
+                FOUR_STEP_SEQUENCE[3] + 1,
+            )
+        };
+
+        if half_frames.contains(&self.frame_cycle) {
+            self.clock_half_frame();
+        }
+
+        if !self.five_step_mode && !self.irq_inhibit && self.frame_cycle == FOUR_STEP_SEQUENCE[3] {
+            self.frame_irq.set(true);
+        }
+
+        if self.frame_cycle >= period {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn output(&self) -> i16 {
+        // no channels are mixed yet, so the output is silent
+        0
+    }
+
+    // advance by one CPU cycle. the DMC may be left waiting on a sample byte, see dmc_dma_address
+    pub(crate) fn step(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        self.step_frame_counter();
+        self.dmc.clock();
+
+        // resample with integer math so the sample count is deterministic
+        self.sample_clock += self.sample_rate;
+        if self.sample_clock >= CPU_CLOCK_NTSC {
+            self.sample_clock -= CPU_CLOCK_NTSC;
+            self.push_sample(self.output());
+        }
+    }
+
+    fn push_sample(&mut self, sample: i16) {
+        self.stats.produced += 1;
+
+        if self.samples.len() >= self.sample_rate as usize {
+            self.stats.dropped += 1;
+
+            match self.overflow_policy {
+                AudioOverflowPolicy::DropOldest => self.samples.pop_front(),
+                AudioOverflowPolicy::DropNewest => return,
+            };
+        }
+
+        self.samples.push_back(sample);
+    }
+
+    pub(crate) fn drain_samples(&mut self) -> Vec<i16> {
+        self.samples.drain(..).collect()
+    }
+
+    pub(crate) fn buffered_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    // the overflow policy kicks in past one second of samples
+    pub(crate) fn buffer_capacity(&self) -> usize {
+        self.sample_rate as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioOverflowPolicy, AudioStats, APU, FIVE_STEP_SEQUENCE, FOUR_STEP_SEQUENCE};
+
+    fn overflow(policy: AudioOverflowPolicy) -> (Vec<i16>, AudioStats) {
+        let mut apu = APU::default();
+        apu.set_sample_rate(4);
+        apu.set_overflow_policy(policy);
+
+        for sample in 1..=6 {
+            apu.push_sample(sample);
+        }
+
+        (apu.drain_samples(), apu.stats())
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = APU::default();
+        // 4-step mode, IRQ enabled. the sequence restarts 3 cycles later
+        apu.write_register(0x4017, 0x00);
+
+        for _ in 0..FOUR_STEP_SEQUENCE[3] + 1 {
+            apu.step();
+        }
+        assert!(!apu.read_irq_line());
+
+        apu.step();
+        assert!(apu.read_irq_line());
+
+        // reading $4015 acknowledges the interrupt
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.read_irq_line());
+
+        // the sequence repeats every 29830 cycles
+        for _ in 0..FOUR_STEP_SEQUENCE[3] {
+            apu.step();
+        }
+        assert!(!apu.read_irq_line());
+        apu.step();
+        assert!(apu.read_irq_line());
+
+        // inhibiting the IRQ clears the flag, and it is never raised again
+        apu.write_register(0x4017, 0x40);
+        assert!(!apu.read_irq_line());
+        for _ in 0..2 * FIVE_STEP_SEQUENCE[4] {
+            apu.step();
+        }
+        assert!(!apu.read_irq_line());
+    }
+
+    #[test]
+    fn test_frame_counter_write() {
+        let mut apu = APU::default();
+        apu.write_register(0x4015, 0b0001);
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.length_counters[0].counter, 254);
+
+        // 4-step mode waits for the sequence
+        apu.write_register(0x4017, 0x00);
+        assert_eq!(apu.length_counters[0].counter, 254);
+
+        // 5-step mode clocks the length counters as soon as it's written
+        apu.write_register(0x4017, 0x80);
+        assert_eq!(apu.length_counters[0].counter, 253);
+
+        // the sequence carries on for a few cycles before restarting, 3 or 4 depending on
+        // whether the write landed on an APU cycle
+        for delay in [3, 4] {
+            for _ in 0..100 {
+                apu.step();
+            }
+            let frame_cycle = apu.frame_cycle;
+            apu.write_register(0x4017, 0x00);
+            for cycle in 1..delay {
+                apu.step();
+                assert_eq!(apu.frame_cycle, frame_cycle + cycle);
+            }
+            apu.step();
+            assert_eq!(apu.frame_cycle, 1);
+        }
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut apu = APU::default();
+        apu.write_register(0x4015, 0b0001);
+        // length index 1 loads 254
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.read_status() & 0x0f, 0b0001);
+
+        // disabled channels ignore loads
+        apu.write_register(0x4007, 0b0000_1000);
+        assert_eq!(apu.read_status() & 0x0f, 0b0001);
+
+        // clocked twice per 4-step sequence
+        for _ in 0..127 * (FOUR_STEP_SEQUENCE[3] + 1) {
+            apu.step();
+        }
+        assert_eq!(apu.read_status() & 0x0f, 0b0000);
+    }
+
+    // run the DMC for `cycles`, serving its reads from `memory`. returns the addresses read
+    fn dmc_reads(apu: &mut APU, memory: impl Fn(u16) -> u8, cycles: usize) -> Vec<u16> {
+        let mut reads = Vec::new();
+        for _ in 0..cycles {
+            apu.step();
+            if let Some(address) = apu.dmc_dma_address() {
+                reads.push(address);
+                apu.dmc_dma_complete(memory(address));
+            }
+        }
+        reads
+    }
+
+    #[test]
+    fn test_dmc_memory_reader() {
+        let mut apu = APU::default();
+        // IRQ enabled, fastest rate, 65 bytes at $FFC0
+        apu.write_register(0x4010, 0x8f);
+        apu.write_register(0x4012, 0xff);
+        apu.write_register(0x4013, 0x04);
+        apu.write_register(0x4015, 0x10);
+        assert_eq!(apu.read_status() & 0x90, 0x10);
+
+        // bytes of all ones walk the output level up
+        let reads = dmc_reads(&mut apu, |_| 0xff, 70 * 8 * 54);
+        // the address wraps around to $8000
+        let expected: Vec<u16> = (0xffc0..=0xffff).chain([0x8000]).collect();
+        assert_eq!(reads, expected);
+        assert_eq!(apu.dmc.output_level, 126);
+
+        // the sample is over, which raises the IRQ until $4015 is written
+        assert!(apu.read_irq_line());
+        assert_eq!(apu.read_status() & 0x90, 0x80);
+        assert!(apu.read_irq_line());
+        apu.write_register(0x4015, 0x00);
+        assert!(!apu.read_irq_line());
+    }
+
+    #[test]
+    fn test_dmc_loop() {
+        let mut apu = APU::default();
+        // loop, IRQ enabled, fastest rate, 1 byte at $C000
+        apu.write_register(0x4010, 0xcf);
+        apu.write_register(0x4012, 0x00);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0x10);
+
+        let reads = dmc_reads(&mut apu, |_| 0x00, 10 * 8 * 54);
+        assert!(reads.len() >= 9);
+        assert!(reads.iter().all(|address| *address == 0xc000));
+        assert!(!apu.read_irq_line());
+        assert_eq!(apu.read_status() & 0x10, 0x10);
+
+        // clearing the enable bit stops the sample
+        apu.write_register(0x4015, 0x00);
+        assert!(dmc_reads(&mut apu, |_| 0x00, 2 * 8 * 54).is_empty());
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let expected_stats = AudioStats {
+            produced: 6,
+            dropped: 2,
+        };
+
+        assert_eq!(
+            overflow(AudioOverflowPolicy::DropOldest),
+            (vec![3, 4, 5, 6], expected_stats)
+        );
+        assert_eq!(
+            overflow(AudioOverflowPolicy::DropNewest),
+            (vec![1, 2, 3, 4], expected_stats)
+        );
+    }
+}
@@ -1,23 +1,131 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
 use crate::apu::APU;
-use crate::cartridge::Mapper;
-use crate::controller::Controller;
+use crate::cartridge::{Mapper, PrgPatchMap, PrgPatches};
+use crate::console::Diagnostic;
+use crate::controller::{ButtonState, Controller, InputDevice, ZapperState};
+use crate::cpu::{AccessKind, MemAccess, RomWrite};
 use crate::ppu::PPU;
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
+
+pub(crate) type IllegalOpcodeCallback = Rc<RefCell<Box<dyn FnMut(u8, u16)>>>;
+pub(crate) type DiagnosticCallback = Rc<RefCell<Box<dyn FnMut(Diagnostic)>>>;
 
 #[derive(Clone)]
 pub(crate) struct MemoryBus {
     pub(crate) mapper: Box<dyn Mapper>,
     pub(crate) ppu: PPU,
     pub(crate) apu: APU,
-    pub(crate) controller: Controller,
+    pub(crate) ports: [Box<dyn InputDevice>; 2],
+    // buttons held on the frontend, forwarded to port one
+    pub(crate) button_state: ButtonState,
+    // where the frontend's Zappers aim and whether they fire, forwarded to both ports
+    pub(crate) zapper_state: ZapperState,
+    // CPU reads and writes for the debugger, only recorded when enabled.
+    // reads only have a shared reference to the bus
+    pub(crate) log_accesses: bool,
+    pub(crate) access_log: RefCell<Vec<MemAccess>>,
+    // the accesses of the last instruction, swapped out of access_log once it completes
+    pub(crate) last_accesses: Vec<MemAccess>,
+    // the CPU cycle count where the DMC next halts the CPU to fetch a sample byte,
+    // for the controller read glitch
+    pub(crate) dmc_dma_cycle: Option<u64>,
+    // called for writes the cartridge drops, shared with every snapshot of the bus
+    pub(crate) rom_write_hook: Option<Rc<dyn Fn(RomWrite)>>,
+    // called with the opcode and PC of each unofficial instruction, shared like rom_write_hook
+    pub(crate) illegal_opcode_callback: Option<IllegalOpcodeCallback>,
+    // called with the likely mistakes in the game spotted by the heuristics, shared the same way
+    pub(crate) diagnostic_callback: Option<DiagnosticCallback>,
+    // the cheat overlay the mapper is wrapped in, if any
+    pub(crate) prg_patches: Option<PrgPatchMap>,
 }
 
 impl MemoryBus {
-    fn new(mapper: Box<dyn Mapper>) -> Self {
+    pub(crate) fn new(mapper: Box<dyn Mapper>) -> Self {
         MemoryBus {
             mapper: mapper,
             ppu: PPU::default(),
             apu: APU::default(),
-            controller: Controller::default(),
+            ports: [
+                Box::new(Controller::default()),
+                Box::new(Controller::default()),
+            ],
+            button_state: ButtonState::default(),
+            zapper_state: ZapperState::default(),
+            log_accesses: false,
+            access_log: RefCell::new(Vec::new()),
+            last_accesses: Vec::new(),
+            dmc_dma_cycle: None,
+            rom_write_hook: None,
+            illegal_opcode_callback: None,
+            diagnostic_callback: None,
+            prg_patches: None,
+        }
+    }
+
+    // swap the cartridge, powering the PPU and APU back on. the frontend's devices,
+    // hooks and settings stay
+    pub(crate) fn insert_cartridge(&mut self, mapper: Box<dyn Mapper>) {
+        self.mapper = mapper;
+        self.prg_patches = None;
+        self.ppu.power_on();
+        self.apu.power_on();
+        self.access_log.get_mut().clear();
+        self.last_accesses.clear();
+    }
+
+    // wrap the mapper in the overlay that reads back the cheats
+    pub(crate) fn install_prg_patches(&mut self, patches: PrgPatchMap) {
+        let mapper = self.mapper.clone();
+        self.mapper = Box::new(PrgPatches::new(mapper, patches.clone()));
+        self.prg_patches = Some(patches);
+    }
+
+    pub(crate) fn log_access(&self, address: u16, value: u8, kind: AccessKind) {
+        if self.log_accesses {
+            self.access_log.borrow_mut().push(MemAccess {
+                address,
+                value,
+                kind,
+            });
+        }
+    }
+
+    pub(crate) fn update_buttons(&mut self, state: ButtonState) {
+        self.button_state = state;
+        self.ports[0].update_buttons(state);
+    }
+
+    pub(crate) fn update_zapper(&mut self, state: ZapperState) {
+        self.zapper_state = state;
+        self.ports
+            .iter_mut()
+            .for_each(|port| port.update_zapper(state));
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        self.mapper.save_state(state);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.ports.iter().for_each(|port| port.save_state(state));
+        state.write_u8(self.button_state.0);
+        state.write_bool(self.zapper_state.trigger);
+        state.write_bool(self.zapper_state.light);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.mapper.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        for port in &mut self.ports {
+            port.load_state(state)?;
         }
+        self.button_state = ButtonState(state.read_u8()?);
+        self.zapper_state.trigger = state.read_bool()?;
+        self.zapper_state.light = state.read_bool()?;
+        Ok(())
     }
 }
@@ -1,8 +1,15 @@
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 
 use dyn_clone::DynClone;
 
-#[derive(Clone, Copy)]
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MirroringMode {
     Horizontal = 0,
     Vertical = 1,
@@ -15,10 +22,12 @@ pub type ProgBank = [u8; 0x4000];
 pub type ChrBank = [u8; 0x2000];
 pub type SaveRamBank = [u8; 0x2000];
 
+// Both variants are reference counted, so snapshots of the console share CHR data.
+// CHR RAM is copy-on-write: the banks are only duplicated once written after a clone.
 #[derive(Clone)]
 pub enum CHR {
     ROM(Rc<Vec<ChrBank>>),
-    RAM(Vec<ChrBank>),
+    RAM(Rc<Vec<ChrBank>>),
 }
 
 impl CHR {
@@ -32,7 +41,7 @@ impl CHR {
     pub fn get_banks_mut(&mut self) -> Option<&mut Vec<ChrBank>> {
         match self {
             CHR::ROM(_) => None,
-            CHR::RAM(banks) => Some(banks),
+            CHR::RAM(banks) => Some(Rc::make_mut(banks)),
         }
     }
 }
@@ -42,20 +51,153 @@ pub struct PRG {
     pub(crate) banks: Vec<ProgBank>,
 }
 
+impl PRG {
+    pub fn new(banks: Vec<ProgBank>) -> Self {
+        PRG { banks }
+    }
+
+    pub fn banks(&self) -> &[ProgBank] {
+        &self.banks
+    }
+}
+
 #[derive(Clone)]
 pub struct Cartridge {
-    pub prg: Rc<PRG>,           // 0x4000 aligned
-    pub chr: CHR,               // 0x2000 aligned
-    pub sram: Vec<SaveRamBank>, // 0x2000 aligned
+    pub prg: Rc<PRG>,               // 0x4000 aligned
+    pub chr: CHR,                   // 0x2000 aligned
+    pub sram: Rc<Vec<SaveRamBank>>, // 0x2000 aligned, copy-on-write
+    pub has_battery: bool,          // SRAM is kept while powered off
+    pub vs_system: bool,            // Vs. System arcade board, with its own PPU palette
+    pub submapper: u8,              // NES 2.0 revision of the board, 0 when unspecified
     pub mirror: MirroringMode,
 }
 
+impl Cartridge {
+    // CPU $6000-$7FFF: 8 KB PRG RAM, if the board has any
+    pub fn read_sram(&self, address: u16) -> u8 {
+        match self.sram.first() {
+            Some(bank) => bank[address as usize % 0x2000],
+            None => 0,
+        }
+    }
+
+    pub fn write_sram(&mut self, address: u16, data: u8) {
+        if !self.sram.is_empty() {
+            Rc::make_mut(&mut self.sram)[0][address as usize % 0x2000] = data;
+        }
+    }
+
+    // only the writable memory is saved, ROM comes from the cartridge being loaded into
+    pub fn save_state(&self, state: &mut StateWriter) {
+        match &self.chr {
+            CHR::ROM(_) => state.write_u16(0),
+            CHR::RAM(banks) => {
+                state.write_u16(banks.len() as u16);
+                banks.iter().for_each(|bank| state.write_bytes(bank));
+            }
+        }
+
+        state.write_u16(self.sram.len() as u16);
+        self.sram.iter().for_each(|bank| state.write_bytes(bank));
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        let chr_banks = state.read_u16()? as usize;
+        match self.chr.get_banks_mut() {
+            Some(banks) if banks.len() == chr_banks => {
+                for bank in banks.iter_mut() {
+                    state.read_bytes(bank)?;
+                }
+            }
+            None if chr_banks == 0 => {}
+            _ => return Err(LoadStateError::Corrupt),
+        }
+
+        let sram_banks = state.read_u16()? as usize;
+        if sram_banks != self.sram.len() {
+            return Err(LoadStateError::Corrupt);
+        }
+        for bank in Rc::make_mut(&mut self.sram).iter_mut() {
+            state.read_bytes(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
 pub trait Mapper: DynClone {
     // fn new(cartridge: Cartridge) -> Self;
+    // iNES mapper number, recorded in save states. boards without one report $FF
+    fn number(&self) -> u8 {
+        0xff
+    }
+
     fn mirror(&self) -> MirroringMode;
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, data: u8);
     fn read_page(&self, page: u8) -> Option<&[u8; 256]>;
+
+    // every address the PPU puts on its bus, from rendering fetches and $2007 accesses.
+    // mappers like MMC3 clock their scanline counter on rising edges of A12 ($1000)
+    fn notify_chr_fetch(&mut self, _address: u16) {}
+
+    // PRG RAM at $6000-$7FFF, which should be saved when battery backed
+    fn has_battery_ram(&self) -> bool {
+        false
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        0
+    }
+
+    // PRG ROM on the board in bytes, every bank and not just the mapped ones
+    fn prg_rom_size(&self) -> usize {
+        0
+    }
+
+    // whether chr_banks is RAM the game writes its tiles to, rather than ROM
+    fn has_chr_ram(&self) -> bool {
+        false
+    }
+
+    // whether a CPU write to $8000-$FFFF does anything, like selecting a bank,
+    // rather than being dropped by ROM. assumed to for boards that don't say
+    fn is_register_write(&self, _address: u16) -> bool {
+        true
+    }
+
+    // Vs. System boards come with an RGB PPU, which orders its palette differently
+    fn is_vs_system(&self) -> bool {
+        false
+    }
+
+    // every CHR bank on the board, not just the ones currently mapped
+    fn chr_banks(&self) -> &[ChrBank] {
+        &[]
+    }
+
+    // None for CHR ROM
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        None
+    }
+
+    // (window start address, bank index) for each window of the CPU address space
+    // backed by PRG ROM, in 16 KB PRG banks. Empty when the mapper doesn't report it
+    fn prg_bank_map(&self) -> Vec<(u16, usize)> {
+        Vec::new()
+    }
+
+    // same as prg_bank_map for the PPU pattern tables, in 8 KB CHR banks
+    fn chr_bank_map(&self) -> Vec<(u16, usize)> {
+        Vec::new()
+    }
+
+    // bank registers and writable cartridge memory
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), LoadStateError> {
+        Ok(())
+    }
 }
 
 dyn_clone::clone_trait_object!(Mapper);
@@ -65,12 +207,18 @@ struct UxROM {
     cartridge: Cartridge,
     first_bank: usize,
     last_bank: usize,
+    // the ROM drives the data bus along with the CPU, so a bank select writes data AND the ROM byte
+    // https://www.nesdev.org/wiki/Bus_conflict
+    bus_conflicts: bool,
 }
 
 impl UxROM {
     fn new(cartridge: Cartridge) -> Self {
         UxROM {
             last_bank: cartridge.prg.banks.len() - 1,
+            // https://www.nesdev.org/wiki/UxROM#Submappers
+            // submapper 2 is UNROM with bus conflicts, 1 is without, and 0 leaves it unspecified
+            bus_conflicts: cartridge.submapper == 2,
             cartridge,
             first_bank: 0,
         }
@@ -78,6 +226,10 @@ impl UxROM {
 }
 
 impl Mapper for UxROM {
+    fn number(&self) -> u8 {
+        2
+    }
+
     fn mirror(&self) -> MirroringMode {
         self.cartridge.mirror
     }
@@ -85,7 +237,8 @@ impl Mapper for UxROM {
     fn read(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x1fff => self.cartridge.chr.get_banks()[0][address as usize],
-            0x2000..=0x7fff => 0,
+            0x2000..=0x5fff => 0,
+            0x6000..=0x7fff => self.cartridge.read_sram(address),
             0x8000..=0xbfff => {
                 // CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
                 self.cartridge.prg.banks[self.first_bank][address as usize % 0x4000]
@@ -104,8 +257,16 @@ impl Mapper for UxROM {
                     banks[0][address as usize] = data;
                 }
             }
-            0x2000..=0x7fff => {}
-            0x8000.. => self.first_bank = data as usize & 0x0f,
+            0x2000..=0x5fff => {}
+            0x6000..=0x7fff => self.cartridge.write_sram(address, data),
+            0x8000.. => {
+                let data = if self.bus_conflicts {
+                    data & self.read(address)
+                } else {
+                    data
+                };
+                self.first_bank = data as usize & 0x0f;
+            }
         }
     }
 
@@ -130,6 +291,56 @@ impl Mapper for UxROM {
             }
         }
     }
+
+    fn has_battery_ram(&self) -> bool {
+        self.cartridge.has_battery
+    }
+
+    fn is_vs_system(&self) -> bool {
+        self.cartridge.vs_system
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        self.cartridge.sram.len() * size_of::<SaveRamBank>()
+    }
+
+    fn prg_rom_size(&self) -> usize {
+        self.cartridge.prg.banks.len() * size_of::<ProgBank>()
+    }
+
+    fn has_chr_ram(&self) -> bool {
+        matches!(self.cartridge.chr, CHR::RAM(_))
+    }
+
+    fn chr_banks(&self) -> &[ChrBank] {
+        self.cartridge.chr.get_banks()
+    }
+
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.cartridge.chr.get_banks_mut().map(Vec::as_mut_slice)
+    }
+
+    fn prg_bank_map(&self) -> Vec<(u16, usize)> {
+        vec![(0x8000, self.first_bank), (0xc000, self.last_bank)]
+    }
+
+    fn chr_bank_map(&self) -> Vec<(u16, usize)> {
+        vec![(0x0000, 0)]
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.first_bank as u8);
+        self.cartridge.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        let first_bank = state.read_u8()? as usize;
+        if first_bank >= self.cartridge.prg.banks.len() {
+            return Err(LoadStateError::Corrupt);
+        }
+        self.first_bank = first_bank;
+        self.cartridge.load_state(state)
+    }
 }
 
 #[derive(Clone)]
@@ -146,6 +357,10 @@ impl NROM {
 }
 
 impl Mapper for NROM {
+    fn number(&self) -> u8 {
+        0
+    }
+
     fn mirror(&self) -> MirroringMode {
         self.uxrom.mirror()
     }
@@ -156,20 +371,385 @@ impl Mapper for NROM {
 
     fn write(&mut self, address: u16, data: u8) {
         match address {
-            0x0000..=0x1fff => self.uxrom.write(address, data),
-            0x2000.. => {}
+            0x0000..=0x1fff | 0x6000..=0x7fff => self.uxrom.write(address, data),
+            0x2000..=0x5fff | 0x8000.. => {}
         };
     }
 
+    fn is_register_write(&self, _address: u16) -> bool {
+        // no bank switching, it's all ROM
+        false
+    }
+
     fn read_page(&self, page: u8) -> Option<&[u8; 256]> {
         self.uxrom.read_page(page)
     }
+
+    fn has_battery_ram(&self) -> bool {
+        self.uxrom.has_battery_ram()
+    }
+
+    fn is_vs_system(&self) -> bool {
+        self.uxrom.is_vs_system()
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        self.uxrom.prg_ram_size()
+    }
+
+    fn prg_rom_size(&self) -> usize {
+        self.uxrom.prg_rom_size()
+    }
+
+    fn has_chr_ram(&self) -> bool {
+        self.uxrom.has_chr_ram()
+    }
+
+    fn chr_banks(&self) -> &[ChrBank] {
+        self.uxrom.chr_banks()
+    }
+
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.uxrom.chr_banks_mut()
+    }
+
+    fn prg_bank_map(&self) -> Vec<(u16, usize)> {
+        self.uxrom.prg_bank_map()
+    }
+
+    fn chr_bank_map(&self) -> Vec<(u16, usize)> {
+        self.uxrom.chr_bank_map()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.uxrom.save_state(state)
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.uxrom.load_state(state)
+    }
+}
+
+#[derive(Clone)]
+struct CNROM {
+    uxrom: UxROM,
+    chr_bank: usize,
+}
+
+impl CNROM {
+    fn new(cartridge: Cartridge) -> Self {
+        CNROM {
+            uxrom: UxROM::new(cartridge),
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for CNROM {
+    fn number(&self) -> u8 {
+        3
+    }
+
+    fn mirror(&self) -> MirroringMode {
+        self.uxrom.mirror()
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1fff => {
+                // PPU $0000-$1FFF: 8 KB switchable CHR ROM bank
+                self.uxrom.cartridge.chr.get_banks()[self.chr_bank][address as usize]
+            }
+            0x2000.. => self.uxrom.read(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x0000..=0x1fff => {
+                if let Some(banks) = self.uxrom.cartridge.chr.get_banks_mut() {
+                    banks[self.chr_bank][address as usize] = data;
+                }
+            }
+            0x2000..=0x5fff => {}
+            0x6000..=0x7fff => self.uxrom.write(address, data),
+            0x8000.. => {
+                // wrap oversized selects, so boards with less CHR than the register can address don't panic
+                self.chr_bank = data as usize % self.uxrom.cartridge.chr.get_banks().len();
+            }
+        }
+    }
+
+    fn read_page(&self, page: u8) -> Option<&[u8; 256]> {
+        self.uxrom.read_page(page)
+    }
+
+    fn has_battery_ram(&self) -> bool {
+        self.uxrom.has_battery_ram()
+    }
+
+    fn is_vs_system(&self) -> bool {
+        self.uxrom.is_vs_system()
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        self.uxrom.prg_ram_size()
+    }
+
+    fn prg_rom_size(&self) -> usize {
+        self.uxrom.prg_rom_size()
+    }
+
+    fn has_chr_ram(&self) -> bool {
+        self.uxrom.has_chr_ram()
+    }
+
+    fn chr_banks(&self) -> &[ChrBank] {
+        self.uxrom.chr_banks()
+    }
+
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.uxrom.chr_banks_mut()
+    }
+
+    fn prg_bank_map(&self) -> Vec<(u16, usize)> {
+        self.uxrom.prg_bank_map()
+    }
+
+    fn chr_bank_map(&self) -> Vec<(u16, usize)> {
+        vec![(0x0000, self.chr_bank)]
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.chr_bank as u8);
+        self.uxrom.save_state(state)
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        let chr_bank = state.read_u8()? as usize;
+        if chr_bank >= self.uxrom.cartridge.chr.get_banks().len() {
+            return Err(LoadStateError::Corrupt);
+        }
+        self.chr_bank = chr_bank;
+        self.uxrom.load_state(state)
+    }
+}
+
+// patched PRG bytes by address, shared by the overlay and every snapshot of it
+pub(crate) type PrgPatchMap = Rc<RefCell<BTreeMap<u16, u8>>>;
+
+// a read overlay on top of another mapper, for patching PRG ROM which can't be written
+#[derive(Clone)]
+pub(crate) struct PrgPatches {
+    mapper: Box<dyn Mapper>,
+    patches: PrgPatchMap,
+}
+
+impl PrgPatches {
+    pub(crate) fn new(mapper: Box<dyn Mapper>, patches: PrgPatchMap) -> Self {
+        PrgPatches { mapper, patches }
+    }
+}
+
+impl Mapper for PrgPatches {
+    fn number(&self) -> u8 {
+        self.mapper.number()
+    }
+
+    fn mirror(&self) -> MirroringMode {
+        self.mapper.mirror()
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        match self.patches.borrow().get(&address) {
+            Some(data) if address >= 0x8000 => *data,
+            _ => self.mapper.read(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.mapper.write(address, data)
+    }
+
+    fn read_page(&self, page: u8) -> Option<&[u8; 256]> {
+        // patched pages are read one byte at a time instead
+        let start = (page as u16) << 8;
+        match self.patches.borrow().range(start..=start | 0xff).next() {
+            Some(_) => None,
+            None => self.mapper.read_page(page),
+        }
+    }
+
+    fn notify_chr_fetch(&mut self, address: u16) {
+        self.mapper.notify_chr_fetch(address)
+    }
+
+    fn is_register_write(&self, address: u16) -> bool {
+        self.mapper.is_register_write(address)
+    }
+
+    fn has_battery_ram(&self) -> bool {
+        self.mapper.has_battery_ram()
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        self.mapper.prg_ram_size()
+    }
+
+    fn prg_rom_size(&self) -> usize {
+        self.mapper.prg_rom_size()
+    }
+
+    fn has_chr_ram(&self) -> bool {
+        self.mapper.has_chr_ram()
+    }
+
+    fn is_vs_system(&self) -> bool {
+        self.mapper.is_vs_system()
+    }
+
+    fn chr_banks(&self) -> &[ChrBank] {
+        self.mapper.chr_banks()
+    }
+
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.mapper.chr_banks_mut()
+    }
+
+    fn prg_bank_map(&self) -> Vec<(u16, usize)> {
+        self.mapper.prg_bank_map()
+    }
+
+    fn chr_bank_map(&self) -> Vec<(u16, usize)> {
+        self.mapper.chr_bank_map()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.mapper.save_state(state)
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.mapper.load_state(state)
+    }
+}
+
+/// Build a cartridge in memory, e.g. for tests, without going through the iNES format.
+/// It gets 8 KB of PRG RAM without a battery, like an iNES image that doesn't say otherwise.
+pub fn from_parts(prg: Vec<ProgBank>, chr: CHR, mirror: MirroringMode) -> Cartridge {
+    Cartridge {
+        prg: Rc::new(PRG::new(prg)),
+        chr,
+        sram: Rc::new(vec![[0u8; 0x2000]]),
+        has_battery: false,
+        vs_system: false,
+        submapper: 0,
+        mirror,
+    }
 }
 
 pub fn new(cartridge: Cartridge, mapper: u8) -> Option<Box<dyn Mapper>> {
     match mapper {
         0 => Some(Box::new(NROM::new(cartridge))),
         2 => Some(Box::new(UxROM::new(cartridge))),
+        3 => Some(Box::new(CNROM::new(cartridge))),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{Cartridge, MirroringMode, CHR, PRG};
+
+    #[test]
+    fn test_chr_ram_copy_on_write() {
+        let chr = CHR::RAM(Rc::new(vec![[0u8; 0x2000]]));
+        let mut written = chr.clone();
+
+        // unmodified clones share the banks
+        assert!(std::ptr::eq(chr.get_banks(), written.get_banks()));
+
+        written.get_banks_mut().unwrap()[0][0x10] = 0xaa;
+        assert!(!std::ptr::eq(chr.get_banks(), written.get_banks()));
+        assert_eq!(chr.get_banks()[0][0x10], 0x00);
+        assert_eq!(written.get_banks()[0][0x10], 0xaa);
+    }
+
+    #[test]
+    fn test_cnrom_chr_bank_wraps() {
+        let cartridge = Cartridge {
+            prg: Rc::new(PRG {
+                banks: vec![[0u8; 0x4000]],
+            }),
+            chr: CHR::ROM(Rc::new(vec![[0x11; 0x2000], [0x22; 0x2000]])),
+            sram: Rc::new(Vec::new()),
+            has_battery: false,
+            vs_system: false,
+            submapper: 0,
+            mirror: MirroringMode::Vertical,
+        };
+        let mut mapper = super::new(cartridge, 3).unwrap();
+
+        mapper.write(0x8000, 0x01);
+        assert_eq!(mapper.read(0x0000), 0x22);
+
+        // bank 5 doesn't exist, wraps to bank 1
+        mapper.write(0x8000, 0x05);
+        assert_eq!(mapper.read(0x1fff), 0x22);
+
+        mapper.write(0xffff, 0xff);
+        assert_eq!(mapper.read(0x1000), 0x22);
+
+        mapper.write(0x8000, 0x04);
+        assert_eq!(mapper.read(0x1000), 0x11);
+    }
+
+    #[test]
+    fn test_uxrom_bank_map() {
+        let cartridge = Cartridge {
+            prg: Rc::new(PRG {
+                banks: vec![[0u8; 0x4000]; 4],
+            }),
+            chr: CHR::RAM(Rc::new(vec![[0u8; 0x2000]])),
+            sram: Rc::new(Vec::new()),
+            has_battery: false,
+            vs_system: false,
+            submapper: 0,
+            mirror: MirroringMode::Vertical,
+        };
+        let mut mapper = super::new(cartridge, 2).unwrap();
+
+        assert_eq!(mapper.prg_bank_map(), vec![(0x8000, 0), (0xc000, 3)]);
+        assert_eq!(mapper.chr_bank_map(), vec![(0x0000, 0)]);
+
+        mapper.write(0x8000, 0x02);
+        assert_eq!(mapper.prg_bank_map(), vec![(0x8000, 2), (0xc000, 3)]);
+        assert!(mapper.is_register_write(0xc000));
+    }
+
+    #[test]
+    fn test_uxrom_bus_conflicts() {
+        let uxrom = |submapper: u8| {
+            // the byte at $C000 only has bit 0 set
+            let mut last_bank = [0u8; 0x4000];
+            last_bank[0] = 0x01;
+            let mut banks = vec![[0u8; 0x4000]; 3];
+            banks.push(last_bank);
+
+            let mut cartridge = super::from_parts(
+                banks,
+                CHR::RAM(Rc::new(vec![[0u8; 0x2000]])),
+                MirroringMode::Vertical,
+            );
+            cartridge.submapper = submapper;
+            super::new(cartridge, 2).unwrap()
+        };
+
+        for (submapper, bank) in [(0, 3), (1, 3), (2, 1)] {
+            let mut mapper = uxrom(submapper);
+            mapper.write(0xc000, 0x03);
+            assert_eq!(mapper.prg_bank_map()[0], (0x8000, bank), "{}", submapper);
+        }
+    }
+}
@@ -1,11 +1,23 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
 use crate::{
-    apu::APU,
+    apu::{AudioOverflowPolicy, AudioStats},
     bus::MemoryBus,
-    cartridge::Mapper,
-    controller::{Button, ButtonState, Controller},
-    cpu::CPU,
-    ppu::{Screen, PPU},
-    snapshot::RewindTape,
+    cartridge::{self, ChrBank, Mapper, MirroringMode, PrgPatchMap},
+    controller::{
+        Button, ButtonState, Controller, InputDevice, InputPollTiming, Port, Zapper, ZapperState,
+    },
+    cpu::{ExecutedInstruction, LogWrite, MemAccess, RomWrite, CPU},
+    ines,
+    ppu::{Screen, ScrollInfo, DOTS_PER_CPU_CYCLE, SCREEN_HEIGHT, SCREEN_WIDTH},
+    snapshot::{
+        LoadStateError, RewindTape, Snapshot, StateReader, StateWriter, SAVE_STATE_MAGIC,
+        SAVE_STATE_VERSION,
+    },
 };
 
 #[derive(Clone)]
@@ -15,45 +27,293 @@ pub struct ConsoleState {
 }
 
 impl ConsoleState {
-    fn step(&mut self, screen: &mut Screen) {
-        let cycles = self.cpu.step(&mut self.bus, None); // Some(&mut stdout()));
-        for _ in 0..cycles {
-            for _ in 0..3 {
+    fn step(
+        &mut self,
+        screen: &mut Screen,
+        trace: Option<&mut (dyn LogWrite + '_)>,
+    ) -> ExecutedInstruction {
+        if self.bus.log_accesses {
+            self.bus.access_log.get_mut().clear();
+        }
+
+        // the controller read glitch needs to know when the DMC halts the CPU ahead of time
+        self.bus.dmc_dma_cycle = if self.bus.ppu.strict_accuracy() {
+            let cycles = self.bus.apu.cycles_until_dmc_dma();
+            cycles.map(|cycles| self.cpu.cycles() + cycles)
+        } else {
+            None
+        };
+
+        let mut executed = self.cpu.step(&mut self.bus, trace);
+        let mut cycles = executed.cycles();
+        let mut cycle = 0;
+        while cycle < cycles {
+            cycle += 1;
+            self.bus.apu.step();
+            for _ in 0..DOTS_PER_CPU_CYCLE {
                 self.bus.ppu.step(self.bus.mapper.as_mut(), screen);
             }
+
+            if cycle == executed.cycles() - 1 {
+                self.cpu.poll_irq(self.bus.apu.read_irq_line());
+            }
+
+            // the DMC fetches its samples from PRG ROM, halting the CPU while the rest keeps running
+            if let Some(address) = self.bus.apu.dmc_dma_address() {
+                let data = self.bus.mapper.read(address);
+                self.bus.apu.dmc_dma_complete(data);
+                let now = self.cpu.cycles() - (cycles - cycle) as u64;
+                cycles += self.cpu.dmc_dma_stall(&mut self.bus, now, data);
+            }
+        }
+
+        executed.add_stall_cycles(cycles - executed.cycles());
+
+        if self.bus.ppu.take_sprite_zero_stall() {
+            if let Some(callback) = &self.bus.diagnostic_callback {
+                (callback.borrow_mut())(Diagnostic::SpriteZeroStall {
+                    frame: self.bus.ppu.frame(),
+                });
+            }
+        }
+
+        if self.bus.log_accesses {
+            core::mem::swap(&mut self.bus.last_accesses, self.bus.access_log.get_mut());
         }
+
+        executed
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.cpu.save_state(state);
+        self.bus.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.cpu.load_state(state)?;
+        self.bus.load_state(state)
+    }
+
+    pub(crate) fn wait_vblank(
+        &mut self,
+        screen: &mut Screen,
+        trace: Option<&mut (dyn LogWrite + '_)>,
+    ) {
+        // the PPU keeps running whatever the CPU does, so a frame always completes
+        let _ = self.wait_vblank_until(screen, trace, u64::MAX);
     }
 
-    pub(crate) fn wait_vblank(&mut self, screen: &mut Screen) {
+    // like `wait_vblank`, giving up once the CPU cycle counter reaches `deadline`
+    pub(crate) fn wait_vblank_until(
+        &mut self,
+        screen: &mut Screen,
+        mut trace: Option<&mut (dyn LogWrite + '_)>,
+        deadline: u64,
+    ) -> Result<(), Timeout> {
         // only return on a positive edge
         while self.bus.ppu.in_vblank {
-            self.step(screen);
+            if self.cpu.cycles() >= deadline {
+                return Err(Timeout);
+            }
+            self.step(screen, trace.as_deref_mut());
         }
 
         while !self.bus.ppu.in_vblank {
-            self.step(screen);
+            if self.cpu.cycles() >= deadline {
+                return Err(Timeout);
+            }
+            self.step(screen, trace.as_deref_mut());
+        }
+
+        Ok(())
+    }
+}
+
+// the board for an iNES image
+fn mapper_from_bytes(data: &[u8]) -> Result<Box<dyn Mapper>, LoadError> {
+    let (cartridge, number) = ines::load_bytes(data).ok_or(LoadError::InvalidImage)?;
+    cartridge::new(cartridge, number).ok_or(LoadError::UnsupportedMapper(number))
+}
+
+// the devices for an NES 2.0 default expansion device, for the ones emulated
+fn expansion_devices(expansion_device: u8) -> Option<[Box<dyn InputDevice>; 2]> {
+    match expansion_device {
+        // standard controllers
+        0x01 => Some([
+            Box::new(Controller::default()),
+            Box::new(Controller::default()),
+        ]),
+        // Zapper in port two
+        0x08 => Some([Box::new(Controller::default()), Box::new(Zapper::default())]),
+        // two Zappers
+        0x09 => Some([Box::new(Zapper::default()), Box::new(Zapper::default())]),
+        _ => None,
+    }
+}
+
+/// Status reported by a test ROM using blargg's protocol
+/// https://github.com/christopherpow/nes-test-roms/blob/master/README.md
+#[derive(Clone, Debug, PartialEq)]
+pub enum TestResult {
+    Running,
+    NeedsReset,
+    Done { code: u8, message: String },
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        matches!(self, TestResult::Done { code: 0, .. })
+    }
+}
+
+/// A PPU address outside of $0000-$3FFF, which can't be preserved across a rewind
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidPpuAddress(pub u16);
+
+impl core::fmt::Display for InvalidPpuAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "PPU address ${:04X} is out of range", self.0)
+    }
+}
+
+impl core::error::Error for InvalidPpuAddress {}
+
+/// The cartridge banks currently mapped in, as (window start address, bank index) pairs.
+/// PRG banks are 16 KB windows of the CPU address space, CHR banks 8 KB windows of the PPU's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BankMap {
+    pub prg: Vec<(u16, usize)>,
+    pub chr: Vec<(u16, usize)>,
+}
+
+/// A PRG ROM patch from `Console::apply_patches`: reads of `address` return `value`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CheatEntry {
+    pub address: u16,
+    pub value: u8,
+}
+
+/// What's on the loaded cartridge, for ROM info displays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CartridgeInfo {
+    pub mapper: u8,
+    pub prg_kb: usize,
+    pub chr_kb: usize,
+    /// CHR is RAM written by the game, rather than ROM
+    pub chr_ram: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadError {
+    /// The ROM couldn't be read
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    /// Not an iNES image, or it's cut short
+    InvalidImage,
+    UnsupportedMapper(u8),
+}
+
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            LoadError::Io(kind) => write!(f, "failed to read ROM: {}", kind),
+            LoadError::InvalidImage => write!(f, "not a valid iNES image"),
+            LoadError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
         }
     }
 }
 
+impl core::error::Error for LoadError {}
+
+/// A likely bug in the running game, reported through `Console::set_diagnostic_callback`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// The CPU kept reading $2002 throughout the picture of `frame` with rendering on, but
+    /// sprite zero never hit: it's probably waiting on a sprite zero that's off screen or
+    /// transparent, and will never see the hit
+    SpriteZeroStall { frame: u64 },
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Diagnostic::SpriteZeroStall { frame } => write!(
+                f,
+                "frame {}: polling $2002 without a sprite zero hit, is sprite zero visible?",
+                frame
+            ),
+        }
+    }
+}
+
+/// A headless run used up the budget from `Console::set_cycle_budget`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeout;
+
+impl core::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "ran out of CPU cycles")
+    }
+}
+
+impl core::error::Error for Timeout {}
+
+const INITIAL_TAPE_STEP: usize = 60; // 1 second buffered
+
 pub struct Console {
-    state: ConsoleState,
+    pub(crate) state: ConsoleState,
     tape: RewindTape,
     screen: Screen,
     in_rewind: bool,
+    rewind_enabled: bool,
+    // addresses that keep their current contents when going back in time
+    rewind_cpu_preserved: Vec<u16>,
+    rewind_ppu_preserved: Vec<u16>,
+    // nestest formatted log of every instruction executed
+    trace: Option<Box<dyn LogWrite>>,
+    // CPU cycle count where headless runs give up
+    cycle_deadline: Option<u64>,
+    // PRG ROM patches, read through the PrgPatches overlay on the mapper
+    cheats: Vec<CheatEntry>,
+    input_poll_timing: InputPollTiming,
+    // buttons from update_buttons waiting for the frame to end
+    pending_buttons: Option<ButtonState>,
 }
 
 impl Console {
-    pub fn snapshot(&self) -> ConsoleState {
-        self.state.clone()
+    /// Capture the current state without advancing emulation. Cheap to take every frame,
+    /// since ROM, CHR and PRG RAM are shared copy-on-write with the running console.
+    pub fn take_snapshot(&self) -> Snapshot {
+        Snapshot(self.state.clone())
+    }
+
+    /// Return to a state from `take_snapshot`, keeping the addresses
+    /// from `set_rewind_preserved_addresses`
+    pub fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.replace_state(snapshot.0);
     }
 
-    pub fn restore_snapshot(
+    /// Keep the current contents of these CPU and PPU addresses when rewinding or restoring
+    /// a snapshot, e.g. to hold on to a menu selection. PPU addresses must be within $0000-$3FFF.
+    pub fn set_rewind_preserved_addresses(
         &mut self,
-        snapshot: ConsoleState,
-        cpu_ignore: &Vec<u16>,
-        ppu_ignore: &Vec<u16>,
-    ) {
+        cpu: Vec<u16>,
+        ppu: Vec<u16>,
+    ) -> Result<(), InvalidPpuAddress> {
+        if let Some(addr) = ppu.iter().find(|addr| **addr > 0x3fff) {
+            return Err(InvalidPpuAddress(*addr));
+        }
+
+        self.rewind_cpu_preserved = cpu;
+        self.rewind_ppu_preserved = ppu;
+        Ok(())
+    }
+
+    fn replace_state(&mut self, state: ConsoleState) {
+        let cpu_ignore = &self.rewind_cpu_preserved;
+        let ppu_ignore = &self.rewind_ppu_preserved;
+
         // read preserved addresses
         let cpu_backup_contents: Vec<u8> = cpu_ignore
             .iter()
@@ -69,7 +329,26 @@ impl Console {
             })
             .collect();
 
-        self.state = snapshot;
+        // the debugging aids belong to the frontend, not to the state
+        let log_accesses = self.state.bus.log_accesses;
+        let rom_write_hook = self.state.bus.rom_write_hook.take();
+        let illegal_opcode_callback = self.state.bus.illegal_opcode_callback.take();
+        let diagnostic_callback = self.state.bus.diagnostic_callback.take();
+        let prg_patches = self.state.bus.prg_patches.take();
+        let ppu = core::mem::replace(&mut self.state, state).bus.ppu;
+        self.state.bus.ppu.keep_debug_settings(&ppu);
+        self.state.bus.log_accesses = log_accesses;
+        self.state.bus.rom_write_hook = rom_write_hook;
+        self.state.bus.illegal_opcode_callback = illegal_opcode_callback;
+        self.state.bus.diagnostic_callback = diagnostic_callback;
+        self.tape.mark_modified();
+
+        // cheats stay on when going back to a state from before they were applied
+        if self.state.bus.prg_patches.is_none() {
+            if let Some(patches) = prg_patches {
+                self.state.bus.install_prg_patches(patches);
+            }
+        }
 
         // restore preserved addresses
         cpu_ignore
@@ -89,48 +368,2758 @@ impl Console {
             });
     }
 
+    /// Press the reset button: the CPU restarts from the reset vector, while RAM is preserved
+    pub fn reset(&mut self) {
+        self.state.bus.ppu.soft_reset();
+        self.state.cpu.soft_reset(&mut self.state.bus);
+        self.tape.mark_modified();
+    }
+
+    /// Step back a frame on the rewind tape, keeping the addresses
+    /// from `set_rewind_preserved_addresses` like `restore_snapshot`
     pub fn rewind(&mut self) {
-        if let Some(prev_state) = self.tape.pop_back(&mut self.screen) {
-            self.state = prev_state;
+        self.rewind_frames(1);
+    }
+
+    /// Stop recording frames for rewind, to save the time and memory, e.g. when benchmarking.
+    /// Disabling it discards the frames already recorded.
+    pub fn set_rewind_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.tape = RewindTape::new(INITIAL_TAPE_STEP);
+        }
+        self.rewind_enabled = enabled;
+    }
+
+    /// Step back `frames` frames on the rewind tape at once, like calling `rewind` repeatedly.
+    /// Returns the number of frames rewound, fewer when the tape runs out.
+    pub fn rewind_frames(&mut self, frames: usize) -> usize {
+        let mut rewound = 0;
+        let mut prev_state = None;
+
+        while rewound < frames {
+            match self.tape.pop_back(&mut self.screen) {
+                Some(state) => prev_state = Some(state),
+                None => break,
+            }
+            rewound += 1;
+        }
+
+        if let Some(prev_state) = prev_state {
+            self.replace_state(prev_state);
             self.in_rewind = true;
         }
+
+        rewound
+    }
+
+    /// Execute exactly one CPU instruction (or interrupt), clocking the rest of the console alongside it
+    pub fn step_instruction(&mut self) -> ExecutedInstruction {
+        self.state.step(&mut self.screen, self.trace.as_deref_mut())
+    }
+
+    /// Step instructions until the CPU's PC reaches `target`, giving up after `max_cycles`,
+    /// or when the budget from `set_cycle_budget` runs out. Returns whether the target was reached.
+    pub fn run_until_pc(&mut self, target: u16, max_cycles: u64) -> bool {
+        let mut cycles: u64 = 0;
+
+        while self.state.cpu.registers().pc != target {
+            if cycles >= max_cycles || self.state.cpu.cycles() >= self.deadline() {
+                return false;
+            }
+
+            cycles += self.step_instruction().cycles() as u64;
+        }
+
+        true
+    }
+
+    /// Read a byte from the CPU address space, without stepping the console.
+    /// I/O registers read as 0, because reading them has side effects.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x401f => 0,
+            _ => self.state.cpu.read_byte(&self.state.bus, addr),
+        }
+    }
+
+    /// Read the result of a blargg-style test ROM from PRG RAM.
+    /// Returns None until the ROM has written the $DE $B0 $61 signature to $6001-$6003.
+    pub fn blargg_result(&self) -> Option<TestResult> {
+        const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+
+        if (0x6001..=0x6003).map(|addr| self.peek(addr)).ne(SIGNATURE) {
+            return None;
+        }
+
+        Some(match self.peek(0x6000) {
+            0x80 => TestResult::Running,
+            0x81 => TestResult::NeedsReset,
+            code => {
+                // null terminated text follows the signature
+                let message = (0x6004..=0x7fff)
+                    .map(|addr| self.peek(addr))
+                    .take_while(|c| *c != 0)
+                    .map(char::from)
+                    .collect();
+
+                TestResult::Done { code, message }
+            }
+        })
+    }
+
+    /// Resample the audio output to `sample_rate` Hz, discarding any buffered samples
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.state.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    /// Choose which samples are dropped once a second of audio is buffered
+    pub fn set_audio_overflow_policy(&mut self, policy: AudioOverflowPolicy) {
+        self.state.bus.apu.set_overflow_policy(policy);
+    }
+
+    /// Count of samples produced and dropped since power on
+    pub fn audio_stats(&self) -> AudioStats {
+        self.state.bus.apu.stats()
+    }
+
+    /// Take all audio samples produced since the last call
+    pub fn drain_audio(&mut self) -> Vec<i16> {
+        self.state.bus.apu.drain_samples()
+    }
+
+    /// Samples waiting to be taken by `drain_audio`, so a frontend can pace emulation
+    /// to keep the buffer from running dry or adding latency
+    pub fn audio_buffer_len(&self) -> usize {
+        self.state.bus.apu.buffered_samples()
+    }
+
+    /// Samples the buffer holds before the overflow policy drops some: one second's worth
+    pub fn audio_buffer_capacity(&self) -> usize {
+        self.state.bus.apu.buffer_capacity()
+    }
+
+    /// Limit headless runs to `cycles` more CPU cycles, so a hung test ROM fails with
+    /// `Timeout` instead of spinning forever. `None` lifts the limit, which is the default.
+    /// `wait_vblank`, `next_screen` and `step_some` aren't limited, for interactive frontends.
+    pub fn set_cycle_budget(&mut self, cycles: Option<u64>) {
+        self.cycle_deadline = cycles.map(|cycles| self.cpu_cycles().saturating_add(cycles));
+    }
+
+    fn deadline(&self) -> u64 {
+        self.cycle_deadline.unwrap_or(u64::MAX)
+    }
+
+    /// Run `frames` frames, recording each one for rewind.
+    /// Fails if the budget from `set_cycle_budget` runs out first.
+    pub fn run_frames(&mut self, frames: usize) -> Result<(), Timeout> {
+        let deadline = self.deadline();
+        for _ in 0..frames {
+            self.state
+                .wait_vblank_until(&mut self.screen, self.trace.as_deref_mut(), deadline)?;
+            self.end_frame();
+        }
+
+        Ok(())
+    }
+
+    /// Run `frames` frames and return all the audio they produced. Audio is always
+    /// sampled at the normal rate, so fast-forwarding keeps the original pitch
+    /// while the frontend only presents the last screen.
+    pub fn run_frames_audio(&mut self, frames: usize) -> Result<Vec<i16>, Timeout> {
+        self.run_frames(frames)?;
+        Ok(self.drain_audio())
+    }
+
+    /// The nametable mirroring currently selected by the mapper
+    pub fn mirroring(&self) -> MirroringMode {
+        self.state.bus.mapper.mirror()
+    }
+
+    /// CPU cycles elapsed since power-on. The counter wraps around on overflow,
+    /// so compare readings with `wrapping_sub`.
+    pub fn cpu_cycles(&self) -> u64 {
+        self.state.cpu.cycles()
+    }
+
+    /// PPU dots that run alongside `cycles` CPU cycles. Only NTSC timing is emulated,
+    /// so this is always `DOTS_PER_CPU_CYCLE` per cycle. Wraps around like `cpu_cycles`.
+    pub fn cpu_cycles_to_dots(&self, cycles: u64) -> u64 {
+        cycles.wrapping_mul(DOTS_PER_CPU_CYCLE)
+    }
+
+    /// Whole CPU cycles that run alongside `dots` PPU dots
+    pub fn dots_to_cpu_cycles(&self, dots: u64) -> u64 {
+        dots / DOTS_PER_CPU_CYCLE
+    }
+
+    /// Whether the PPU is in vertical blank, from scanline 241 until the pre-render scanline.
+    /// For frontends that poll after `step_some` instead of blocking in `wait_vblank`.
+    pub fn in_vblank(&self) -> bool {
+        self.state.bus.ppu.in_vblank
+    }
+
+    /// Frames the PPU has completed since power-on
+    pub fn frame_count(&self) -> u64 {
+        self.state.bus.ppu.frame()
+    }
+
+    /// Whether the current frame is odd, which drops a dot from the pre-render scanline
+    /// while rendering. Kept in save states, so replays started from one stay dot exact.
+    pub fn frame_parity(&self) -> bool {
+        self.frame_count() % 2 == 1
+    }
+
+    /// Whether the cartridge keeps its PRG RAM while powered off, so the frontend should save it
+    pub fn has_battery_ram(&self) -> bool {
+        self.state.bus.mapper.has_battery_ram()
+    }
+
+    /// Size of the cartridge PRG RAM in bytes, 0 if there is none
+    pub fn prg_ram_size(&self) -> usize {
+        self.state.bus.mapper.prg_ram_size()
+    }
+
+    /// The mapper and memory sizes of the loaded cartridge
+    pub fn cartridge_info(&self) -> CartridgeInfo {
+        let mapper = &self.state.bus.mapper;
+        CartridgeInfo {
+            mapper: mapper.number(),
+            prg_kb: mapper.prg_rom_size() / 1024,
+            chr_kb: size_of_val(mapper.chr_banks()) / 1024,
+            chr_ram: mapper.has_chr_ram(),
+        }
+    }
+
+    /// Every CHR bank of the cartridge, for tile viewers and editors
+    pub fn chr_banks(&self) -> &[ChrBank] {
+        self.state.bus.mapper.chr_banks()
+    }
+
+    /// Every CHR bank of the cartridge, writable for boards with CHR RAM.
+    /// Returns None for CHR ROM.
+    pub fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.tape.mark_modified();
+        self.state.bus.mapper.chr_banks_mut()
+    }
+
+    /// Patch the CPU address space, for ROM hacks and cheats. PRG ROM at $8000-$FFFF reads back
+    /// the patched values from then on, whichever bank is mapped in, and the patches are listed
+    /// by `cheats`. Addresses below $8000, like RAM and PRG RAM, are written once as the CPU would.
+    pub fn apply_patches(&mut self, patches: &[(u16, u8)]) {
+        for (addr, data) in patches {
+            match addr {
+                0x8000.. => self.cheats.push(CheatEntry {
+                    address: *addr,
+                    value: *data,
+                }),
+                _ => self.state.cpu.write_byte(&mut self.state.bus, *addr, *data),
+            }
+        }
+
+        if self.state.bus.prg_patches.is_none() && !self.cheats.is_empty() {
+            self.state.bus.install_prg_patches(PrgPatchMap::default());
+        }
+        self.update_prg_patches();
+    }
+
+    /// The PRG ROM patches from `apply_patches`, in the order they were applied
+    pub fn cheats(&self) -> &[CheatEntry] {
+        &self.cheats
+    }
+
+    /// Remove one of the `cheats`, so its address reads back the ROM again.
+    /// Returns None when the index is out of range.
+    pub fn remove_cheat(&mut self, index: usize) -> Option<CheatEntry> {
+        if index >= self.cheats.len() {
+            return None;
+        }
+
+        let cheat = self.cheats.remove(index);
+        self.update_prg_patches();
+        Some(cheat)
+    }
+
+    /// Remove all of the `cheats`
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+        self.update_prg_patches();
+    }
+
+    // refill the overlay from the cheat list, later cheats to the same address winning
+    fn update_prg_patches(&mut self) {
+        if let Some(patches) = &self.state.bus.prg_patches {
+            let mut patches = patches.borrow_mut();
+            patches.clear();
+            patches.extend(self.cheats.iter().map(|cheat| (cheat.address, cheat.value)));
+        }
+        self.tape.mark_modified();
+    }
+
+    /// Which PRG and CHR banks are mapped in right now, for memory map debuggers
+    pub fn bank_map(&self) -> BankMap {
+        BankMap {
+            prg: self.state.bus.mapper.prg_bank_map(),
+            chr: self.state.bus.mapper.chr_bank_map(),
+        }
+    }
+
+    /// The (scanline, dot) where the sprite zero hit fired in the current frame, if it did.
+    /// Dot 1 renders the leftmost pixel. Cleared on the pre-render scanline.
+    pub fn last_sprite_zero_hit(&self) -> Option<(u16, u16)> {
+        self.state.bus.ppu.sprite_zero_hit_at()
+    }
+
+    /// The scroll position the PPU is rendering from, decoded from its VRAM address `v`
+    /// and fine X. Mid-frame, it reflects the dot the PPU has reached.
+    pub fn ppu_scroll(&self) -> ScrollInfo {
+        self.state.bus.ppu.scroll()
+    }
+
+    /// Palette indexes of scanline `y` as the next frame would draw it from the current VRAM,
+    /// OAM, palette and scroll, for stepping through mid-frame splits in a debugger.
+    /// The emulation isn't advanced and the mapper doesn't see the tile fetches.
+    /// None for the scanlines past the visible picture, which draw nothing.
+    pub fn render_scanline_preview(&self, y: u16) -> Option<[u8; SCREEN_WIDTH]> {
+        if y as usize >= SCREEN_HEIGHT {
+            return None;
+        }
+
+        let bus = &self.state.bus;
+        Some(bus.ppu.preview_scanline(bus.mapper.as_ref(), y))
+    }
+
+    /// The background and sprite pattern tables selected by PPUCTRL, each $0000 or $1000.
+    /// 8x16 sprites pick their table from the tile index instead.
+    pub fn pattern_table_bases(&self) -> (u16, u16) {
+        self.state.bus.ppu.pattern_table_bases()
+    }
+
+    /// Height of sprites in pixels, 8 or 16 depending on PPUCTRL
+    pub fn sprite_height(&self) -> u8 {
+        self.state.bus.ppu.sprite_height()
+    }
+
+    /// A hash of everything a save state holds, to catch two consoles drifting apart in
+    /// netplay or replays: consoles fed the same ROM and inputs match after every frame.
+    /// The rewind tape and the rendered screen aren't part of it.
+    pub fn state_checksum(&self) -> u64 {
+        let mut state = StateWriter::default();
+        self.state.save_state(&mut state);
+
+        // FNV-1a, like `Screen::checksum`
+        state
+            .into_bytes()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    /// Serialize the console into a versioned save state.
+    /// Only RAM and registers are saved, so it can only be loaded into a console running the same game.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state.write_bytes(&SAVE_STATE_MAGIC);
+        state.write_u16(SAVE_STATE_VERSION);
+        state.write_u8(self.state.bus.mapper.number());
+        self.state.save_state(&mut state);
+        state.into_bytes()
+    }
+
+    /// Restore a save state produced by `save_state`.
+    /// States from another format version or mapper are rejected, and the console is left untouched on error.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), LoadStateError> {
+        let mut state = StateReader::new(data);
+
+        let mut magic = [0u8; 4];
+        state
+            .read_bytes(&mut magic)
+            .map_err(|_| LoadStateError::BadMagic)?;
+        if magic != SAVE_STATE_MAGIC {
+            return Err(LoadStateError::BadMagic);
+        }
+
+        let version = state.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(LoadStateError::UnsupportedVersion(version));
+        }
+
+        let expected = self.state.bus.mapper.number();
+        let found = state.read_u8()?;
+        if found != expected {
+            return Err(LoadStateError::WrongMapper { expected, found });
+        }
+
+        let mut loaded = self.state.clone();
+        loaded.load_state(&mut state)?;
+        if !state.is_empty() {
+            return Err(LoadStateError::Corrupt);
+        }
+
+        self.state = loaded;
+        self.tape.mark_modified();
+        Ok(())
+    }
+
+    /// Log every instruction executed from now on to `writer`, in the format of the nestest log.
+    /// Instructions replayed while rewinding aren't logged.
+    pub fn enable_cpu_trace(&mut self, writer: Box<dyn LogWrite>) {
+        self.trace = Some(writer);
+    }
+
+    /// Stop logging instructions, dropping the writer
+    pub fn disable_cpu_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Record every read and write the CPU makes, for `last_instruction_accesses`.
+    /// Disabled by default, since it slows down every access.
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.state.bus.log_accesses = enabled;
+        self.state.bus.access_log.get_mut().clear();
+        self.state.bus.last_accesses.clear();
+    }
+
+    /// Call `hook` whenever the CPU writes to PRG ROM and the write is dropped, as opposed
+    /// to selecting a bank or writing to RAM. Games doing this are usually buggy. None removes it.
+    pub fn set_rom_write_hook(&mut self, hook: Option<Box<dyn Fn(RomWrite)>>) {
+        self.state.bus.rom_write_hook = hook.map(Rc::from);
+    }
+
+    /// Call `callback` with the opcode and PC of every unofficial instruction executed.
+    /// Games rarely use them, so they often mean the CPU went off executing data. None removes it.
+    pub fn set_illegal_opcode_callback(&mut self, callback: Option<Box<dyn FnMut(u8, u16)>>) {
+        self.state.bus.illegal_opcode_callback =
+            callback.map(|callback| Rc::new(RefCell::new(callback)));
+    }
+
+    /// Call `callback` when a heuristic spots a likely bug in the running game, see `Diagnostic`.
+    /// Meant for homebrew developers, it can misfire on games that do odd things. None removes it.
+    pub fn set_diagnostic_callback(&mut self, callback: Option<Box<dyn FnMut(Diagnostic)>>) {
+        self.state.bus.diagnostic_callback =
+            callback.map(|callback| Rc::new(RefCell::new(callback)));
+    }
+
+    /// The reads and writes of the last instruction or interrupt, in order.
+    /// Empty unless enabled with `set_access_logging`.
+    pub fn last_instruction_accesses(&self) -> &[MemAccess] {
+        &self.state.bus.last_accesses
+    }
+
+    /// Skip a dot on odd frames while rendering, like the NTSC PPU. Enabled by default;
+    /// disabling it makes every frame 341x262 dots, for comparing against emulators without it.
+    pub fn set_odd_frame_skip(&mut self, enabled: bool) {
+        self.state.bus.ppu.set_odd_frame_skip(enabled);
+        self.tape.mark_modified();
     }
 
+    /// Render with the palette of the RGB PPU used by Vs. System arcade boards.
+    /// Enabled automatically for cartridges flagged as Vs. System.
+    pub fn set_vs_system(&mut self, enabled: bool) {
+        self.state.bus.ppu.set_vs_palette(enabled);
+        self.tape.mark_modified();
+    }
+
+    /// Swap the red and green emphasis bits of PPUMASK, like the PAL PPU. There's no PAL
+    /// timing or palette, so this only fixes the colors of PAL games that use emphasis.
+    pub fn set_pal_emphasis(&mut self, enabled: bool) {
+        self.state.bus.ppu.set_pal_emphasis(enabled);
+        self.tape.mark_modified();
+    }
+
+    /// Hide the background or sprites, whatever PPUMASK says, to pick apart rendering bugs.
+    /// Hidden layers still take part in sprite 0 hits, so games run the same.
+    pub fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.state.bus.ppu.set_layer_visibility(background, sprites);
+        self.tape.mark_modified();
+    }
+
+    /// Emulate hardware quirks that only test ROMs and badly behaved games depend on.
+    /// Enabled by default, turning it off trades them for simpler emulation. It controls:
+    /// - PPU open bus: write only registers and the low bits of PPUSTATUS read back the
+    ///   last value on the bus, instead of 0
+    /// - OAMDATA writes during rendering are dropped, and bump OAMADDR to the next sprite
+    /// - a DMC sample fetch landing on a controller read clocks the controller twice,
+    ///   dropping a bit
+    /// - a DMC sample fetch during an OAM DMA shares its halt, taking 1 to 3 cycles instead
+    ///   of 3 or 4, and one on the OAM DMA's last get writes its sample byte to the last
+    ///   byte of OAM copied
+    pub fn set_strict_accuracy(&mut self, enabled: bool) {
+        self.state.bus.ppu.set_strict_accuracy(enabled);
+        self.tape.mark_modified();
+    }
+
+    /// Let each bit of the PPU open bus fade to 0 once nothing has driven it for `frames`
+    /// frames, like the real data bus does after about 600 ms (36 frames). Disabled by default,
+    /// the open bus then holds its value forever, which is all games need.
+    pub fn set_open_bus_decay(&mut self, enabled: bool, frames: u32) {
+        self.state
+            .bus
+            .ppu
+            .set_open_bus_decay(enabled.then_some(frames));
+        self.tape.mark_modified();
+    }
+
+    /// Hold down `state` on the controller in port one, from now or from the next frame
+    /// depending on `set_input_poll_timing`
     pub fn update_buttons(&mut self, state: ButtonState) {
-        self.state.bus.controller.update_buttons(state);
+        match self.input_poll_timing {
+            InputPollTiming::Immediate => self.state.bus.update_buttons(state),
+            InputPollTiming::NextFrame => self.pending_buttons = Some(state),
+        }
     }
 
-    pub fn new(mapper: Box<dyn Mapper>) -> Self {
-        const INITIAL_TAPE_STEP: usize = 60; // 1 second buffered
+    /// Choose whether `update_buttons` takes effect right away, or once the current frame ends.
+    /// Frames end at vblank, or when the PPU wraps around to the first scanline for `step_some`.
+    /// Switching to `Immediate` applies any buttons still waiting for the next frame.
+    pub fn set_input_poll_timing(&mut self, timing: InputPollTiming) {
+        self.input_poll_timing = timing;
+        if timing == InputPollTiming::Immediate {
+            self.latch_pending_buttons();
+        }
+    }
 
-        let mut console = Console {
-            state: ConsoleState {
-                bus: MemoryBus {
-                    mapper,
-                    ppu: PPU::default(),
-                    apu: APU::default(),
-                    controller: Controller::default(),
-                },
-                cpu: CPU::default(),
-            },
-            screen: Screen::default(),
-            tape: RewindTape::new(INITIAL_TAPE_STEP),
-            in_rewind: false,
-        };
+    fn latch_pending_buttons(&mut self) {
+        if let Some(buttons) = self.pending_buttons.take() {
+            self.state.bus.update_buttons(buttons);
+        }
+    }
 
-        console.state.bus.ppu.reset();
-        console.state.cpu.reset(&mut console.state.bus);
-        console
+    /// Plug a device into a controller port, replacing the current one
+    pub fn set_input_device(&mut self, port: Port, device: Box<dyn InputDevice>) {
+        self.state.bus.ports[port as usize] = device;
+        self.tape.mark_modified();
     }
 
-    pub fn next_screen(&mut self) -> &Screen {
-        self.state.wait_vblank(&mut self.screen);
+    /// Aim and fire the Zappers plugged into either port
+    pub fn update_zapper(&mut self, state: ZapperState) {
+        self.state.bus.update_zapper(state);
+    }
 
-        if !self.in_rewind {
-            self.tape.push_back(self.state.clone());
+    // plug in the devices an NES 2.0 header asks for, keeping the current ones otherwise
+    fn install_expansion_devices(&mut self, data: &[u8]) {
+        if let Some([one, two]) = expansion_devices(ines::default_expansion_device(data)) {
+            self.set_input_device(Port::One, one);
+            self.set_input_device(Port::Two, two);
         }
+    }
 
-        self.in_rewind = false;
+    /// Read an iNES ROM and power on a console with it
+    #[cfg(feature = "std")]
+    pub fn load_rom<R: std::io::Read>(reader: &mut R) -> Result<Console, LoadError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|err| LoadError::Io(err.kind()))?;
+
+        Console::load_rom_bytes(&data)
+    }
+
+    /// Power on a console with an iNES ROM already in memory. The Zappers of NES 2.0 images
+    /// that list them as their default expansion device are plugged in.
+    pub fn load_rom_bytes(data: &[u8]) -> Result<Console, LoadError> {
+        let mut console = Console::new(mapper_from_bytes(data)?);
+        console.install_expansion_devices(data);
+        Ok(console)
+    }
+
+    /// Power the console back on with another cartridge, like `Console::new`, but keeping the
+    /// frontend's side: input devices, callbacks, audio and video settings stay as they were.
+    /// The rewind tape, cheats, preserved addresses and cycle budget belong to the previous
+    /// game, and are cleared.
+    pub fn insert_cartridge(&mut self, mapper: Box<dyn Mapper>) {
+        let vs_system = mapper.is_vs_system();
+        self.state.bus.insert_cartridge(mapper);
+        self.state.cpu = CPU::default();
+        self.state.cpu.reset(&mut self.state.bus);
+        self.set_vs_system(vs_system);
+
+        self.screen = Screen::default();
+        self.tape = RewindTape::new(INITIAL_TAPE_STEP);
+        self.in_rewind = false;
+        self.rewind_cpu_preserved.clear();
+        self.rewind_ppu_preserved.clear();
+        self.cycle_deadline = None;
+        self.cheats.clear();
+        self.pending_buttons = None;
+    }
+
+    /// Read an iNES ROM from memory and `insert_cartridge` it, e.g. for a ROM browser.
+    /// NES 2.0 images naming their default expansion device get its devices plugged in.
+    /// On error the current game keeps running.
+    pub fn load_and_insert(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        self.insert_cartridge(mapper_from_bytes(data)?);
+        self.install_expansion_devices(data);
+        Ok(())
+    }
+
+    pub fn new(mapper: Box<dyn Mapper>) -> Self {
+        let mut console = Console {
+            state: ConsoleState {
+                bus: MemoryBus::new(mapper),
+                cpu: CPU::default(),
+            },
+            screen: Screen::default(),
+            tape: RewindTape::new(INITIAL_TAPE_STEP),
+            in_rewind: false,
+            rewind_enabled: true,
+            rewind_cpu_preserved: Vec::new(),
+            rewind_ppu_preserved: Vec::new(),
+            trace: None,
+            cycle_deadline: None,
+            cheats: Vec::new(),
+            input_poll_timing: InputPollTiming::Immediate,
+            pending_buttons: None,
+        };
+
+        console.state.bus.ppu.reset();
+        console.state.cpu.reset(&mut console.state.bus);
+        console.set_vs_system(console.state.bus.mapper.is_vs_system());
+        console
+    }
+
+    /// Advance emulation to the start of the next vblank, rendering the frame and
+    /// recording it for rewind
+    pub fn wait_vblank(&mut self) {
+        self.state
+            .wait_vblank(&mut self.screen, self.trace.as_deref_mut());
+        self.end_frame();
+    }
+
+    /// Advance emulation by a frame, like `wait_vblank`, and return the rendered screen
+    pub fn next_screen(&mut self) -> &Screen {
+        self.wait_vblank();
+        &self.screen
+    }
+
+    /// Run instructions for at least `cycles` CPU cycles, without blocking for a whole frame.
+    /// Stops early when a frame completes, so a frontend can poll `is_frame_complete` after each call.
+    /// Frames are recorded for rewind as vblank starts, like `wait_vblank`.
+    /// Returns the number of CPU cycles run.
+    pub fn step_some(&mut self, cycles: u64) -> u64 {
+        let mut elapsed: u64 = 0;
+        self.state.bus.ppu.frame_complete = false;
+
+        while elapsed < cycles {
+            elapsed += self.step_recording().cycles() as u64;
+
+            if self.state.bus.ppu.frame_complete {
+                break;
+            }
+        }
+
+        elapsed
+    }
+
+    /// Run instructions until the PPU moves on from its current scanline, and return that scanline,
+    /// for raster effect debugging. Whole instructions run, so it stops a few dots into the next
+    /// scanline, or a few scanlines later after an OAM DMA. Frames are recorded for rewind like
+    /// `step_some`.
+    pub fn step_scanline(&mut self) -> u16 {
+        let (scanline, _) = self.state.bus.ppu.position();
+        self.state.bus.ppu.frame_complete = false;
+
+        while self.state.bus.ppu.position().0 == scanline {
+            self.step_recording();
+        }
+
+        scanline
+    }
+
+    // Run an instruction, recording the frame once it reaches vblank. The tape's replays
+    // run to the start of vblank too, so the frames have to be recorded at the same point
+    fn step_recording(&mut self) -> ExecutedInstruction {
+        let in_vblank = self.state.bus.ppu.in_vblank;
+        let executed = self.state.step(&mut self.screen, self.trace.as_deref_mut());
+
+        if !in_vblank && self.state.bus.ppu.in_vblank {
+            self.end_frame();
+        }
+        executed
+    }
+
+    /// Whether the last call to `step_some` or `step_scanline` finished a frame, which is then
+    /// ready in `screen`
+    pub fn is_frame_complete(&self) -> bool {
+        self.state.bus.ppu.frame_complete
+    }
+
+    /// Save the most recently rendered screen as a PNG indexed by the NES palette,
+    /// for archiving and diffing frames pixel for pixel
+    #[cfg(feature = "png")]
+    pub fn save_frame_indexed_png<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.screen.write_indexed_png(std::io::BufWriter::new(file))
+    }
+
+    /// Run `frames` frames headlessly, saving each one in `out_dir` as an RGB PNG named
+    /// frame_000001.png, frame_000002.png..., to diff whole runs before and after a change.
+    /// Running out of the budget from `set_cycle_budget` fails with `ErrorKind::TimedOut`.
+    #[cfg(feature = "png")]
+    pub fn run_frames_dumping<P: AsRef<std::path::Path>>(
+        &mut self,
+        frames: usize,
+        out_dir: P,
+    ) -> std::io::Result<()> {
+        let out_dir = out_dir.as_ref();
+        std::fs::create_dir_all(out_dir)?;
+
+        for frame in 1..=frames {
+            self.run_frames(1)
+                .map_err(|timeout| std::io::Error::new(std::io::ErrorKind::TimedOut, timeout))?;
+
+            let file = std::fs::File::create(out_dir.join(format!("frame_{:06}.png", frame)))?;
+            self.screen.write_png(std::io::BufWriter::new(file))?;
+        }
+
+        Ok(())
+    }
+
+    /// The most recently rendered screen, without stepping the console,
+    /// so a paused frontend can present it again
+    pub fn screen(&self) -> &Screen {
         &self.screen
     }
+
+    fn end_frame(&mut self) {
+        if self.rewind_enabled && !self.in_rewind {
+            self.tape
+                .push_back(self.state.clone(), self.screen.checksum());
+        }
+
+        self.in_rewind = false;
+        self.latch_pending_buttons();
+    }
+}
+
+/// Direct access to PPU and CPU state, to set up a known scene in rendering tests,
+/// or start conformance test ROMs like nestest from their entry point
+#[cfg(any(test, feature = "debug"))]
+impl Console {
+    /// Continue execution from `pc`, like nestest's automated mode at $C000
+    pub fn debug_set_pc(&mut self, pc: u16) {
+        self.state.cpu.debug_set_pc(pc);
+        self.tape.mark_modified();
+    }
+
+    /// Set the stack pointer
+    pub fn debug_set_sp(&mut self, sp: u8) {
+        self.state.cpu.debug_set_sp(sp);
+        self.tape.mark_modified();
+    }
+
+    /// Set the current and temporary VRAM addresses, and fine X scroll
+    pub fn debug_set_scroll(&mut self, v: u16, t: u16, fine_x: u8) {
+        self.state.bus.ppu.debug_set_scroll(v, t, fine_x);
+        self.tape.mark_modified();
+    }
+
+    /// Set PPUCTRL and PPUMASK, without triggering an NMI
+    pub fn debug_set_registers(&mut self, control: u8, mask: u8) {
+        self.state.bus.ppu.debug_set_registers(control, mask);
+        self.tape.mark_modified();
+    }
+
+    /// Write `data` to the PPU address space starting at `addr`: CHR, nametables and palette RAM
+    pub fn debug_write_ppu(&mut self, addr: u16, data: &[u8]) {
+        for (offset, byte) in data.iter().enumerate() {
+            let addr = addr.wrapping_add(offset as u16) & 0x3fff;
+            self.state
+                .bus
+                .ppu
+                .write_byte(self.state.bus.mapper.as_mut(), addr, *byte);
+        }
+        self.tape.mark_modified();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use crate::cartridge::{self, Cartridge, ChrBank, Mapper, MirroringMode, CHR};
+    use crate::controller::{
+        Button, ButtonState, InputDevice, InputPollTiming, Port, Zapper, ZapperState,
+    };
+
+    use crate::cpu::{AccessKind, ExecutedInstruction, MemAccess, Registers, RomWrite};
+    use crate::ines::{self, ines_image};
+    use crate::instructions::{AddressingMode, Opcode};
+
+    use crate::ppu::{Screen, CPU_CYCLES_PER_FRAME, DOTS_PER_CPU_CYCLE, DOTS_PER_FRAME};
+    use crate::snapshot::LoadStateError;
+
+    use super::{
+        CartridgeInfo, CheatEntry, Console, ConsoleState, Diagnostic, InvalidPpuAddress, LoadError,
+        ScrollInfo, TestResult, Timeout,
+    };
+
+    // Build an NROM cartridge with `program` at $8000, and all vectors pointing to it
+    pub(crate) fn test_cartridge(program: &[u8]) -> Cartridge {
+        let mut bank = [0u8; 0x4000];
+        bank[..program.len()].copy_from_slice(program);
+        bank[0x3ffa..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+
+        cartridge::from_parts(
+            vec![bank],
+            CHR::RAM(Rc::new(vec![[0u8; 0x2000]])),
+            MirroringMode::Horizontal,
+        )
+    }
+
+    pub(crate) fn test_console(program: &[u8]) -> Console {
+        Console::new(cartridge::new(test_cartridge(program), 0).unwrap())
+    }
+
+    #[test]
+    fn test_rewind_chr_ram() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+
+        let write_chr = |console: &mut Console, data: u8| {
+            console.chr_banks_mut().unwrap()[0][0x0123] = data;
+        };
+        let read_chr = |console: &Console| {
+            let bus = &console.state.bus;
+            bus.ppu.read_byte(bus.mapper.as_ref(), 0x0123)
+        };
+
+        for data in 1..=3 {
+            write_chr(&mut console, data);
+            console.next_screen();
+        }
+
+        for data in (1..=3).rev() {
+            console.rewind();
+            assert_eq!(read_chr(&console), data);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_size() {
+        // JMP $8000, on a board with 8KB of CHR RAM
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.chr_banks_mut().unwrap()[0][0x0123] = 0x42;
+        let snapshot = console.state.clone();
+
+        // the CHR RAM is shared until it's written, so a frame on the rewind tape copies
+        // ~5KB of console state, where it used to copy ~13KB with the CHR RAM
+        let chr = |state: &ConsoleState| state.bus.mapper.chr_banks().as_ptr();
+        assert_eq!(chr(&snapshot), chr(&console.state));
+        let copied =
+            std::mem::size_of_val(&snapshot) + std::mem::size_of_val(&*snapshot.bus.mapper);
+        assert!(
+            copied < std::mem::size_of::<ChrBank>(),
+            "copied {} B",
+            copied
+        );
+    }
+
+    #[test]
+    fn test_rewind_frames() {
+        // INC $10; JMP $8000
+        let mut console = test_console(&[0xe6, 0x10, 0x4c, 0x00, 0x80]);
+        let mut saved = Vec::new();
+        for _ in 0..100 {
+            console.next_screen();
+            saved.push(console.save_state());
+        }
+
+        // the first frame rewound is the current one
+        assert_eq!(console.rewind_frames(30), 30);
+        assert!(console.save_state() == saved[70]);
+
+        assert_eq!(console.rewind_frames(1000), 70);
+        assert!(console.save_state() == saved[0]);
+    }
+
+    #[test]
+    fn test_rewind_disabled() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(2).unwrap();
+
+        console.set_rewind_enabled(false);
+        console.run_frames(2).unwrap();
+        assert_eq!(console.rewind_frames(10), 0);
+
+        console.set_rewind_enabled(true);
+        console.run_frames(2).unwrap();
+        assert_eq!(console.rewind_frames(10), 2);
+    }
+
+    #[test]
+    fn test_rewind_ignore() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(2).unwrap();
+
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x0020, 0x42);
+        state.cpu.write_byte(&mut state.bus, 0x0021, 0x43);
+
+        console
+            .set_rewind_preserved_addresses(vec![0x0020], vec![0x2000])
+            .unwrap();
+        let bus = &mut console.state.bus;
+        bus.ppu.write_byte(bus.mapper.as_mut(), 0x2000, 0x44);
+
+        console.rewind();
+        assert_eq!(console.peek(0x0020), 0x42);
+        assert_eq!(console.peek(0x0021), 0x00);
+        let bus = &console.state.bus;
+        assert_eq!(bus.ppu.read_byte(bus.mapper.as_ref(), 0x2000), 0x44);
+
+        assert_eq!(
+            console.set_rewind_preserved_addresses(Vec::new(), vec![0x4000]),
+            Err(InvalidPpuAddress(0x4000))
+        );
+    }
+
+    #[test]
+    fn test_rewind_idle_frames() {
+        const FRAMES: usize = 200;
+
+        // JMP $8000, with rendering off the screen never changes
+        let mut idle = test_console(&[0x4c, 0x00, 0x80]);
+        let mut saved = Vec::new();
+        for _ in 0..FRAMES {
+            idle.next_screen();
+            saved.push(idle.save_state());
+        }
+
+        let mut active = test_console(&[0x4c, 0x00, 0x80]);
+        for frame in 0..FRAMES {
+            let mut buttons = ButtonState::default();
+            if frame % 2 == 0 {
+                buttons.set(Button::A);
+            }
+            active.update_buttons(buttons);
+            active.next_screen();
+        }
+
+        assert!(idle.tape.stored_states() * 4 < active.tape.stored_states());
+
+        // every idle frame is replayed exactly
+        for state in saved.iter().rev() {
+            idle.rewind();
+            assert!(idle.save_state() == *state);
+        }
+    }
+
+    #[test]
+    fn test_rewind_zapper() {
+        // LDA $4017; STA $10; JMP $8000
+        let mut console = test_console(&[0xad, 0x17, 0x40, 0x85, 0x10, 0x4c, 0x00, 0x80]);
+        console.set_input_device(Port::Two, Box::new(Zapper::default()));
+
+        let mut saved = Vec::new();
+        for frame in 0..100 {
+            console.update_zapper(ZapperState {
+                trigger: (20..25).contains(&frame),
+                light: false,
+            });
+            console.next_screen();
+            saved.push(console.save_state());
+        }
+        assert_eq!(console.peek(0x10) & 0x10, 0);
+
+        // the trigger pull is replayed from the tape, far behind the latest snapshots
+        for (frame, state) in saved.iter().enumerate().rev() {
+            console.rewind();
+            assert!(console.save_state() == *state);
+            assert_eq!(console.peek(0x10) & 0x10 != 0, (20..25).contains(&frame));
+        }
+    }
+
+    #[test]
+    fn test_rewind_stuck_replay() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.next_screen();
+        while console.state.bus.ppu.position().0 != 0 {
+            console.step_instruction();
+        }
+
+        // a stale vblank flag at the top of the frame, so the replay would run for two frames
+        console.state.bus.ppu.in_vblank = true;
+        console.tape.mark_modified();
+        console.end_frame();
+        let stuck_cycles = console.cpu_cycles();
+
+        // idle frames, replayed from the stuck state when rewinding
+        console.next_screen();
+        console.next_screen();
+
+        // the replay gives up within its budget, and hands back the stuck state
+        console.rewind();
+        assert_eq!(console.cpu_cycles(), stuck_cycles);
+        console.rewind();
+        assert_eq!(console.cpu_cycles(), stuck_cycles);
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        // LDA #$01; STA $00; INX; JMP $8000
+        let mut console = test_console(&[0xa9, 0x01, 0x85, 0x00, 0xe8, 0x4c, 0x00, 0x80]);
+
+        let expected = [
+            (
+                0x8000,
+                Opcode::LDA,
+                AddressingMode::Immediate,
+                Some(0x8001),
+                2,
+            ),
+            (
+                0x8002,
+                Opcode::STA,
+                AddressingMode::ZeroPage,
+                Some(0x0000),
+                3,
+            ),
+            (0x8004, Opcode::INX, AddressingMode::Implied, None, 2),
+            (
+                0x8005,
+                Opcode::JMP,
+                AddressingMode::Absolute,
+                Some(0x8000),
+                3,
+            ),
+        ];
+
+        for (pc, opcode, addressing_mode, operand, cycles) in expected {
+            match console.step_instruction() {
+                ExecutedInstruction::Instruction {
+                    pc: executed_pc,
+                    opcode: executed_opcode,
+                    addressing_mode: executed_mode,
+                    operand: executed_operand,
+                    cycles: executed_cycles,
+                    ..
+                } => {
+                    assert_eq!(executed_pc, pc);
+                    assert_eq!(executed_opcode, opcode);
+                    assert_eq!(executed_mode, addressing_mode);
+                    assert_eq!(executed_operand, operand);
+                    assert_eq!(executed_cycles, cycles);
+                }
+                interrupt => panic!("unexpected {:?}", interrupt),
+            }
+        }
+
+        assert_eq!(
+            console.state.cpu.registers(),
+            Registers {
+                pc: 0x8000,
+                a: 0x01,
+                x: 0x01,
+                y: 0x00,
+                status: 0x24,
+                sp: 0xfd,
+            }
+        );
+        assert_eq!(console.state.cpu.ram[0], 0x01);
+    }
+
+    #[test]
+    fn test_run_until_pc() {
+        // 8000: LDX #$05
+        // 8002: DEX
+        // 8003: BNE $8002
+        // 8005: STX $10
+        // 8007: JMP $8007
+        let mut console =
+            test_console(&[0xa2, 0x05, 0xca, 0xd0, 0xfd, 0x86, 0x10, 0x4c, 0x07, 0x80]);
+        console.state.cpu.ram[0x10] = 0xff;
+
+        assert!(console.run_until_pc(0x8007, 1000));
+        assert_eq!(console.state.cpu.registers().x, 0);
+        assert_eq!(console.peek(0x0010), 0x00);
+        assert_eq!(console.peek(0x8008), 0x07);
+
+        assert!(!console.run_until_pc(0x9000, 1000));
+    }
+
+    #[test]
+    fn test_cycle_budget() {
+        // 8000: JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(1).unwrap();
+
+        console.set_cycle_budget(Some(10_000));
+        assert!(!console.run_until_pc(0x9000, u64::MAX));
+        let spent = console.cpu_cycles();
+        assert_eq!(console.run_frames(1000), Err(Timeout));
+        assert_eq!(console.run_frames_audio(1), Err(Timeout));
+        assert_eq!(console.cpu_cycles(), spent);
+
+        // the interactive path isn't limited
+        console.next_screen();
+
+        console.set_cycle_budget(None);
+        assert_eq!(console.run_frames(2), Ok(()));
+    }
+
+    #[test]
+    fn test_debug_set_pc() {
+        // 8000: JMP $8000
+        // 8003: PHA
+        let mut console = test_console(&[0x4c, 0x00, 0x80, 0x48]);
+        console.debug_set_pc(0x8003);
+        console.debug_set_sp(0x80);
+
+        match console.step_instruction() {
+            ExecutedInstruction::Instruction {
+                pc,
+                opcode,
+                registers,
+                ..
+            } => {
+                assert_eq!(pc, 0x8003);
+                assert_eq!(opcode, Opcode::PHA);
+                assert_eq!(registers.pc, 0x8004);
+                assert_eq!(registers.sp, 0x7f);
+            }
+            executed => panic!("{:?}", executed),
+        }
+        assert_eq!(console.peek(0x0180), console.state.cpu.registers().a);
+    }
+
+    #[test]
+    fn test_state_checksum() {
+        // strobe the controller, then count presses of A in $10
+        // 8000: LDA #$01; STA $4016; LSR A; STA $4016; LDA $4016; AND #$01; CLC; ADC $10;
+        //       STA $10; JMP $8000
+        let program = [
+            0xa9, 0x01, 0x8d, 0x16, 0x40, 0x4a, 0x8d, 0x16, 0x40, 0xad, 0x16, 0x40, 0x29, 0x01,
+            0x18, 0x65, 0x10, 0x85, 0x10, 0x4c, 0x00, 0x80,
+        ];
+        let mut first = test_console(&program);
+        let mut second = test_console(&program);
+        // the rewind tape isn't state
+        second.set_rewind_enabled(false);
+        assert_eq!(first.state_checksum(), second.state_checksum());
+
+        for frame in 0..10 {
+            let mut buttons = ButtonState::default();
+            if frame % 3 == 0 {
+                buttons.set(Button::A);
+            }
+            first.update_buttons(buttons);
+            second.update_buttons(buttons);
+            first.run_frames(1).unwrap();
+            second.run_frames(1).unwrap();
+            assert_eq!(
+                first.state_checksum(),
+                second.state_checksum(),
+                "frame {}",
+                frame
+            );
+        }
+
+        // a press only one of them saw
+        let mut buttons = ButtonState::default();
+        buttons.set(Button::A);
+        first.update_buttons(buttons);
+        second.update_buttons(ButtonState::default());
+        first.run_frames(1).unwrap();
+        second.run_frames(1).unwrap();
+        assert_ne!(first.state_checksum(), second.state_checksum());
+    }
+
+    #[test]
+    fn test_blargg_result() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        assert_eq!(console.blargg_result(), None);
+
+        let mut write = |addr: u16, data: &[u8]| {
+            for (offset, byte) in data.iter().enumerate() {
+                console.state.bus.mapper.write(addr + offset as u16, *byte);
+            }
+        };
+        write(0x6000, &[0x80, 0xde, 0xb0, 0x61]);
+        write(0x6004, b"Passed\n\0");
+        assert_eq!(console.blargg_result(), Some(TestResult::Running));
+
+        console.state.bus.mapper.write(0x6000, 0x00);
+        let result = console.blargg_result().unwrap();
+        assert!(result.passed());
+        assert_eq!(
+            result,
+            TestResult::Done {
+                code: 0,
+                message: String::from("Passed\n")
+            }
+        );
+    }
+
+    #[test]
+    fn test_audio_buffer_len() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        assert_eq!(console.audio_buffer_capacity(), 44_100);
+        assert_eq!(console.audio_buffer_len(), 0);
+
+        // the first frame after power on is short
+        console.run_frames(1).unwrap();
+        let first_frame = console.audio_buffer_len();
+
+        console.run_frames(1).unwrap();
+        let one_frame = console.audio_buffer_len() - first_frame;
+        assert!((733..=735).contains(&one_frame), "{}", one_frame);
+
+        console.drain_audio();
+        assert_eq!(console.audio_buffer_len(), 0);
+
+        console.set_audio_sample_rate(48_000);
+        assert_eq!(console.audio_buffer_capacity(), 48_000);
+    }
+
+    #[test]
+    fn test_run_frames_audio() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+
+        // the first frame after power on is short
+        console.run_frames_audio(1).unwrap();
+
+        // 44100 Hz / ~60.1 frames per second
+        let one_frame = console.run_frames_audio(1).unwrap().len();
+        assert!((733..=735).contains(&one_frame), "{}", one_frame);
+
+        let three_frames = console.run_frames_audio(3).unwrap().len();
+        assert!(
+            three_frames.abs_diff(one_frame * 3) <= 3,
+            "{}",
+            three_frames
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct MockDevice {
+        reads: Cell<u8>,
+        // shared with the test, the device itself belongs to the console
+        last_write: Rc<Cell<u8>>,
+    }
+
+    impl InputDevice for MockDevice {
+        fn write_strobe(&mut self, data: u8) {
+            self.last_write.set(data);
+        }
+
+        fn read(&self) -> u8 {
+            // alternate 1, 0, 1, 0...
+            let reads = self.reads.get();
+            self.reads.set(reads + 1);
+            (reads % 2 == 0) as u8
+        }
+    }
+
+    #[test]
+    fn test_input_poll_timing() {
+        let a_pressed = |console: &mut Console| {
+            let state = &mut console.state;
+            state.cpu.write_byte(&mut state.bus, 0x4016, 0x01);
+            state.cpu.write_byte(&mut state.bus, 0x4016, 0x00);
+            state.cpu.read_byte(&state.bus, 0x4016) == 1
+        };
+        let mut buttons = ButtonState::default();
+        buttons.set(Button::A);
+
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.set_input_poll_timing(InputPollTiming::NextFrame);
+        console.run_frames(1).unwrap();
+        for _ in 0..1000 {
+            console.step_instruction();
+        }
+
+        // pressed mid-frame, only polled from the next frame on
+        console.update_buttons(buttons);
+        assert!(!a_pressed(&mut console));
+        for _ in 0..1000 {
+            console.step_instruction();
+        }
+        assert!(!a_pressed(&mut console));
+        console.run_frames(1).unwrap();
+        assert!(a_pressed(&mut console));
+
+        // switching back applies the buttons still waiting
+        console.update_buttons(ButtonState::default());
+        assert!(a_pressed(&mut console));
+        console.set_input_poll_timing(InputPollTiming::Immediate);
+        assert!(!a_pressed(&mut console));
+        console.update_buttons(buttons);
+        assert!(a_pressed(&mut console));
+    }
+
+    #[test]
+    fn test_set_input_device() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.set_input_device(Port::Two, Box::new(MockDevice::default()));
+
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x01);
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x00);
+
+        let bits: Vec<u8> = (0..4)
+            .map(|_| state.cpu.read_byte(&state.bus, 0x4017))
+            .collect();
+        assert_eq!(bits, [1, 0, 1, 0]);
+
+        // port one still has a standard controller with nothing pressed
+        assert_eq!(state.cpu.read_byte(&state.bus, 0x4016), 0);
+    }
+
+    #[test]
+    fn test_input_device_write() {
+        // LDA #$07; STA $4016; JMP $8005
+        let mut console = test_console(&[0xa9, 0x07, 0x8d, 0x16, 0x40, 0x4c, 0x05, 0x80]);
+        let device = MockDevice::default();
+        let last_write = device.last_write.clone();
+        console.set_input_device(Port::Two, Box::new(device));
+
+        console.step_instruction();
+        console.step_instruction();
+        // the expansion port bits come along with the strobe
+        assert_eq!(last_write.get(), 0x07);
+    }
+
+    #[test]
+    fn test_strict_accuracy_open_bus() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        let state = &mut console.state;
+
+        // OAMADDR is write only, so reading it returns what was written
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x6a);
+        assert_eq!(state.cpu.read_byte(&state.bus, 0x2003), 0x6a);
+
+        console.set_strict_accuracy(false);
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x6a);
+        assert_eq!(state.cpu.read_byte(&state.bus, 0x2003), 0x00);
+    }
+
+    #[test]
+    fn test_layer_visibility_kept_on_rewind() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21, 0x16]);
+        console.debug_write_ppu(0x0000, &[0xff; 8]);
+        console.debug_set_registers(0, 0b0000_1010);
+        console.run_frames(3).unwrap();
+        let snapshot = console.take_snapshot();
+
+        // the background is hidden, showing the backdrop, from before and after the override
+        console.set_layer_visibility(false, true);
+        console.run_frames(2).unwrap();
+        console.rewind_frames(2);
+        console.run_frames(1).unwrap();
+        assert_eq!(console.screen().index_at(0, 0), 0x21);
+
+        console.restore_snapshot(snapshot);
+        console.run_frames(1).unwrap();
+        assert_eq!(console.screen().index_at(0, 0), 0x21);
+
+        console.set_layer_visibility(true, true);
+        console.run_frames(1).unwrap();
+        assert_eq!(console.screen().index_at(0, 0), 0x16);
+    }
+
+    #[test]
+    fn test_strict_accuracy_kept_on_restore() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(2).unwrap();
+        let snapshot = console.take_snapshot();
+
+        // write only registers read back the open bus with strict accuracy, 0 without
+        let read_open_bus = |console: &mut Console| {
+            let state = &mut console.state;
+            state.cpu.write_byte(&mut state.bus, 0x2005, 0xab);
+            state.cpu.read_byte(&state.bus, 0x2005)
+        };
+        assert_eq!(read_open_bus(&mut console), 0xab);
+
+        // turned off after the snapshot and the frames on the tape, it stays off
+        console.set_strict_accuracy(false);
+        console.restore_snapshot(snapshot);
+        assert_eq!(read_open_bus(&mut console), 0x00);
+        console.rewind_frames(2);
+        assert_eq!(read_open_bus(&mut console), 0x00);
+    }
+
+    #[test]
+    fn test_open_bus_decay() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.set_open_bus_decay(true, 2);
+        console.run_frames(1).unwrap();
+
+        let read_open_bus = |console: &Console| {
+            let state = &console.state;
+            state.cpu.read_byte(&state.bus, 0x2003)
+        };
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0xff);
+        assert_eq!(read_open_bus(&console), 0xff);
+
+        // reading back the open bus doesn't refresh it
+        console.run_frames(1).unwrap();
+        assert_eq!(read_open_bus(&console), 0xff);
+        console.run_frames(1).unwrap();
+        assert_eq!(read_open_bus(&console), 0x00);
+
+        // without decay the value stays on the bus
+        console.set_open_bus_decay(false, 2);
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0xff);
+        console.run_frames(3).unwrap();
+        assert_eq!(read_open_bus(&console), 0xff);
+
+        // the setting survives restoring a snapshot taken before it changed
+        let snapshot = console.take_snapshot();
+        console.set_open_bus_decay(true, 2);
+        console.restore_snapshot(snapshot);
+        console.run_frames(3).unwrap();
+        assert_eq!(read_open_bus(&console), 0x00);
+    }
+
+    #[test]
+    fn test_dmc_dma_controller_glitch() {
+        // $8000: NOP, $8001: LDA $00, $8003: LDA $4016
+        let first_read = |strict_accuracy: bool| {
+            let mut console = test_console(&[0xea, 0xa5, 0x00, 0xad, 0x16, 0x40]);
+            console.set_strict_accuracy(strict_accuracy);
+            let mut buttons = ButtonState::default();
+            buttons.set(Button::A);
+            buttons.set(Button::Select);
+            console.update_buttons(buttons);
+
+            // looping 17 byte samples at the fastest rate
+            let state = &mut console.state;
+            for (addr, data) in [
+                (0x4010, 0x4f),
+                (0x4012, 0x00),
+                (0x4013, 0x01),
+                (0x4015, 0x10),
+            ] {
+                state.cpu.write_byte(&mut state.bus, addr, data);
+            }
+            state.cpu.write_byte(&mut state.bus, 0x4016, 0x01);
+            state.cpu.write_byte(&mut state.bus, 0x4016, 0x00);
+            console.debug_set_pc(0x8000);
+            console.step_instruction();
+
+            // 2 and 3 cycle instructions until the next fetch is 4 cycles away, on the last
+            // cycle of LDA $4016, where it reads the controller
+            loop {
+                match console.state.bus.apu.cycles_until_dmc_dma().unwrap() {
+                    4 => break,
+                    cycles if cycles % 2 == 1 => console.debug_set_pc(0x8001),
+                    _ => console.debug_set_pc(0x8000),
+                }
+                console.step_instruction();
+            }
+            console.debug_set_pc(0x8003);
+            console.step_instruction();
+
+            let state = &console.state;
+            let rest: Vec<u8> = (0..2)
+                .map(|_| state.cpu.read_byte(&state.bus, 0x4016))
+                .collect();
+            (console.state.cpu.registers().a, rest)
+        };
+
+        // A, B, Select, Start: the A button is lost, and B comes first
+        assert_eq!(first_read(true), (0, vec![1, 0]));
+        assert_eq!(first_read(false), (1, vec![0, 1]));
+    }
+
+    #[test]
+    fn test_controller_ninth_read() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        let mut buttons = ButtonState::default();
+        buttons.set(Button::Right);
+        console.update_buttons(buttons);
+
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x01);
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x00);
+
+        let bits: Vec<u8> = (0..9)
+            .map(|_| state.cpu.read_byte(&state.bus, 0x4016))
+            .collect();
+
+        // Right is the 8th bit, and the standard controller is an official one
+        assert_eq!(bits, [0, 0, 0, 0, 0, 0, 0, 1, 1]);
+    }
+
+    // switches between one-screen mirroring modes on any write, like AxROM
+    #[derive(Clone)]
+    struct MirroringMapper {
+        prg: [u8; 0x4000],
+        mirror: MirroringMode,
+    }
+
+    impl Mapper for MirroringMapper {
+        fn number(&self) -> u8 {
+            7
+        }
+
+        fn mirror(&self) -> MirroringMode {
+            self.mirror
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            match address {
+                0x8000.. => self.prg[address as usize % 0x4000],
+                _ => 0,
+            }
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            if address >= 0x8000 {
+                self.mirror = if data & 0x10 == 0 {
+                    MirroringMode::SingleScreenLowerBank
+                } else {
+                    MirroringMode::SingleScreenUpperBank
+                };
+            }
+        }
+
+        fn read_page(&self, _page: u8) -> Option<&[u8; 256]> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut console = Console::new(Box::new(MirroringMapper {
+            prg: [0; 0x4000],
+            mirror: MirroringMode::SingleScreenLowerBank,
+        }));
+        assert_eq!(console.mirroring(), MirroringMode::SingleScreenLowerBank);
+
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x8000, 0x10);
+        assert_eq!(console.mirroring(), MirroringMode::SingleScreenUpperBank);
+    }
+
+    #[test]
+    fn test_save_state() {
+        // INX; JMP $8000
+        let mut console = test_console(&[0xe8, 0x4c, 0x00, 0x80]);
+        console.run_frames(1).unwrap();
+        let saved = console.save_state();
+        let x = console.state.cpu.registers().x;
+
+        console.run_frames(1).unwrap();
+        assert_ne!(console.state.cpu.registers().x, x);
+
+        console.load_state(&saved).unwrap();
+        assert_eq!(console.state.cpu.registers().x, x);
+        assert_eq!(console.save_state(), saved);
+
+        let mut bad_magic = saved.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            console.load_state(&bad_magic),
+            Err(LoadStateError::BadMagic)
+        );
+
+        let mut future = saved.clone();
+        future[4] = 0xff;
+        assert!(matches!(
+            console.load_state(&future),
+            Err(LoadStateError::UnsupportedVersion(_))
+        ));
+
+        assert_eq!(
+            console.load_state(&saved[..saved.len() - 1]),
+            Err(LoadStateError::Truncated)
+        );
+
+        let mut cnrom = Console::new(cartridge::new(test_cartridge(&[0xea]), 3).unwrap());
+        assert_eq!(
+            cnrom.load_state(&saved),
+            Err(LoadStateError::WrongMapper {
+                expected: 3,
+                found: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_step_some() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+
+        let mut completed = 0;
+        let mut previous_complete = false;
+        let mut cycles: u64 = 0;
+        // a frame is ~29781 CPU cycles, stop halfway through the fourth
+        while cycles < 3 * 29781 + 15000 {
+            let elapsed = console.step_some(100);
+            assert!((1..100 + 7).contains(&elapsed));
+            cycles += elapsed;
+
+            if console.is_frame_complete() {
+                // frames are too long to complete twice in a row
+                assert!(!previous_complete);
+                completed += 1;
+            }
+            previous_complete = console.is_frame_complete();
+        }
+
+        assert_eq!(completed, 3);
+    }
+
+    #[test]
+    fn test_step_some_screen() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21]);
+        console.debug_set_registers(0, 0b0000_1000); // show background
+
+        while !console.is_frame_complete() {
+            console.step_some(1000);
+        }
+
+        // the completed frame is in the console's screen
+        assert!(console
+            .screen()
+            .pixels
+            .iter()
+            .flatten()
+            .all(|color| *color == 0x21));
+    }
+
+    #[test]
+    fn test_step_some_rewind() {
+        // keep writing a new backdrop color, so every frame looks different
+        // LDA #$3F; STA $2006; LDA #$00; STA $2006; INC $10; LDA $10; STA $2007; JMP $8000
+        let mut console = test_console(&[
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20, 0xe6, 0x10, 0xa5, 0x10,
+            0x8d, 0x07, 0x20, 0x4c, 0x00, 0x80,
+        ]);
+
+        // the cycle count and screen of each frame as vblank starts, where wait_vblank stops
+        let mut recorded = Vec::new();
+        while recorded.len() < 100 {
+            let in_vblank = console.state.bus.ppu.in_vblank;
+            console.step_some(1);
+            if !in_vblank && console.state.bus.ppu.in_vblank {
+                recorded.push((console.cpu_cycles(), console.screen().checksum()));
+            }
+        }
+
+        // the rewound frames, fresh snapshots or replayed ones, continue like the recording
+        let mut latest = recorded.len() - 1;
+        for frames in [2, 30, 40, 20] {
+            console.rewind_frames(frames);
+            let frame = latest + 1 - frames;
+            assert_eq!(console.cpu_cycles(), recorded[frame].0);
+
+            console.next_screen();
+            assert_eq!(
+                (console.cpu_cycles(), console.screen().checksum()),
+                recorded[frame + 1]
+            );
+            latest = frame - 1;
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_cycles() {
+        // LDA #$02; STA $4014; JMP $8000
+        let mut console = test_console(&[0xa9, 0x02, 0x8d, 0x14, 0x40, 0x4c, 0x00, 0x80]);
+        console.step_instruction();
+        let cycles = console.cpu_cycles();
+        let executed = console.step_instruction();
+
+        // STA takes 4 cycles, then the CPU halts for 513 or 514
+        assert!((4 + 513..=4 + 514).contains(&executed.cycles()));
+        assert_eq!(console.cpu_cycles() - cycles, executed.cycles() as u64);
+    }
+
+    #[test]
+    fn test_dmc_dma_stall() {
+        // fastest rate, 17 bytes at $C000
+        // LDA #$0F; STA $4010; LDA #$01; STA $4013; LDA #$10; STA $4015; JMP $800F
+        let mut console = test_console(&[
+            0xa9, 0x0f, 0x8d, 0x10, 0x40, 0xa9, 0x01, 0x8d, 0x13, 0x40, 0xa9, 0x10, 0x8d, 0x15,
+            0x40, 0x4c, 0x0f, 0x80,
+        ]);
+        for _ in 0..5 {
+            console.step_instruction();
+        }
+        let cycles = console.cpu_cycles();
+
+        // the first fetch happens as soon as the sample starts
+        let executed = console.step_instruction();
+        assert!((4 + 3..=4 + 4).contains(&executed.cycles()));
+
+        let dmc_active = |console: &Console| console.state.bus.apu.read_status() & 0x10 != 0;
+        let mut jumps = 0;
+        while dmc_active(&console) {
+            let executed = console.step_instruction();
+            assert!((3..=3 + 4).contains(&executed.cycles()));
+            jumps += 1;
+        }
+
+        // each of the 17 fetches halted the CPU for 3 or 4 cycles
+        let stalled = console.cpu_cycles() - cycles - 4 - 3 * jumps;
+        assert!((17 * 3..=17 * 4).contains(&stalled), "stalled {}", stalled);
+    }
+
+    #[test]
+    fn test_dmc_dma_during_oam_dma() {
+        // $8000: LDA #$02, $8002: NOP, $8003: STA $4014, samples of $5A at $8040
+        let mut program = vec![0xa9, 0x02, 0xea, 0x8d, 0x14, 0x40];
+        program.resize(0x40, 0x00);
+        program.extend([0x5a; 17]);
+
+        // the cycles STA $4014 takes, and the last sprite byte, when a DMC fetch lands
+        // `before_end` cycles before the end of its OAM DMA
+        let overlap = |strict_accuracy: bool, before_end: u64| {
+            let mut console = test_console(&program);
+            console.set_strict_accuracy(strict_accuracy);
+            console.step_instruction();
+
+            // sprites of $11 at $0200, and looping 17 byte samples at the slowest rate
+            let state = &mut console.state;
+            state.cpu.ram[0x200..0x300].fill(0x11);
+            for (addr, data) in [
+                (0x4010, 0x40),
+                (0x4012, 0x01),
+                (0x4013, 0x01),
+                (0x4015, 0x10),
+            ] {
+                state.cpu.write_byte(&mut state.bus, addr, data);
+            }
+
+            // NOPs until the next fetch lands where it should. fetches are on get cycles,
+            // an odd number of cycles before the last put
+            loop {
+                let cycles = console.cpu_cycles();
+                let end = cycles + 4 + 513 + (cycles + 4) % 2;
+                let fetch = cycles + console.state.bus.apu.cycles_until_dmc_dma().unwrap();
+                if fetch + before_end == end {
+                    break;
+                }
+                console.debug_set_pc(0x8002);
+                console.step_instruction();
+            }
+            console.debug_set_pc(0x8003);
+            let executed = console.step_instruction();
+
+            let state = &mut console.state;
+            state.cpu.write_byte(&mut state.bus, 0x2003, 0xff);
+            (executed.cycles(), state.cpu.read_byte(&state.bus, 0x2004))
+        };
+
+        // STA takes 4 cycles, then the OAM DMA 514 with its alignment cycle. without strict
+        // accuracy, the fetch halts the CPU for 3 or 4 more as if the OAM DMA wasn't there
+        assert_eq!(overlap(false, 1), (4 + 514 + 4, 0x11));
+        assert_eq!(overlap(false, 201), (4 + 514 + 4, 0x11));
+
+        // sharing the halt, a fetch takes a get cycle and one to realign, or just one cycle
+        // on the second to last get
+        assert_eq!(overlap(true, 201), (4 + 514 + 2, 0x11));
+        assert_eq!(overlap(true, 3), (4 + 514 + 1, 0x11));
+
+        // on the last get it runs after the OAM DMA, whose last put writes the sample byte
+        assert_eq!(overlap(true, 1), (4 + 514 + 3, 0x5a));
+    }
+
+    #[test]
+    fn test_in_vblank() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+
+        let mut vblank_steps = 0;
+        // two frames worth of 3 cycle JMPs
+        for _ in 0..2 * 29_781 / 3 {
+            console.step_instruction();
+
+            // set on dot 1 of scanline 241, cleared on dot 1 of the pre-render scanline
+            let position = console.state.bus.ppu.position();
+            let expected = (241, 2) <= position && position <= (261, 1);
+            assert_eq!(console.in_vblank(), expected, "at {:?}", position);
+            vblank_steps += expected as usize;
+        }
+        assert!(vblank_steps > 0);
+    }
+
+    #[test]
+    fn test_ppu_scroll() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        let bus = &mut console.state.bus;
+
+        // a mid-frame scroll split sharing the write latch: nametable 1,
+        // Y = 11 * 8 + 6 as the second $2005 write, then X = 15 * 8 + 5 as the first
+        for (addr, data) in [
+            (0x2006, 0x04),
+            (0x2005, 0x5e),
+            (0x2005, 0x7d),
+            (0x2006, 0x6f),
+        ] {
+            console.state.cpu.write_byte(bus, addr, data);
+        }
+
+        assert_eq!(
+            console.ppu_scroll(),
+            ScrollInfo {
+                coarse_x: 15,
+                coarse_y: 11,
+                fine_x: 5,
+                fine_y: 6,
+                nametable: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_step_scanline() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(1).unwrap();
+        while console.step_scanline() != 261 {}
+
+        let frame = console.frame_count();
+        let scanlines: Vec<u16> = (0..262).map(|_| console.step_scanline()).collect();
+        assert_eq!(scanlines, (0..262).collect::<Vec<u16>>());
+        assert_eq!(console.frame_count(), frame + 1);
+        assert!(console.is_frame_complete());
+    }
+
+    #[test]
+    fn test_mid_frame_palette_write() {
+        // 8000: JMP $8000
+        // 8003: blank the screen and write the backdrop color, like a raster split:
+        //       LDA #$00; STA $2001; LDA #$3F; STA $2006; LDA #$00; STA $2006; LDA #$16;
+        //       STA $2007; LDA #$00; STA $2006; STA $2006; LDA #$0A; STA $2001; JMP $8024
+        let program = [
+            0x4c, 0x00, 0x80, 0xa9, 0x00, 0x8d, 0x01, 0x20, 0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9,
+            0x00, 0x8d, 0x06, 0x20, 0xa9, 0x16, 0x8d, 0x07, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20,
+            0x8d, 0x06, 0x20, 0xa9, 0x0a, 0x8d, 0x01, 0x20, 0x4c, 0x24, 0x80,
+        ];
+        let mut console = test_console(&program);
+        console.debug_write_ppu(0x3f00, &[0x21]);
+        console.debug_set_registers(0, 0b0000_1010);
+        console.run_frames(2).unwrap();
+
+        while console.step_scanline() != 99 {}
+        console.debug_set_pc(0x8003);
+        console.run_frames(1).unwrap();
+
+        // the new color shows from the dots after the write, until the end of the frame
+        let screen = console.screen();
+        let row = |y: usize| (0..256).map(move |x| screen.index_at(x, y));
+        assert!((0..100).flat_map(row).all(|index| index == 0x21));
+        assert!((101..240).flat_map(row).all(|index| index == 0x16));
+
+        // while blanked, the split shows palette entry 1 ($00) as the write left v on $3F01
+        let split: Vec<u8> = row(100).collect();
+        assert_eq!(split[0], 0x21);
+        assert!(split.contains(&0x00));
+        assert_eq!(split[255], 0x16);
+    }
+
+    #[test]
+    fn test_render_scanline_preview() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21, 0x16]);
+        console.debug_write_ppu(0x2000, &[0x01]);
+        console.debug_write_ppu(0x0010, &[0xff; 8]);
+        console.debug_set_registers(0, 0b0000_1010);
+        console.run_frames(2).unwrap();
+
+        let preview = console.render_scanline_preview(3).unwrap();
+        assert_eq!(
+            preview[..9],
+            [0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x21]
+        );
+        assert!((0..256).all(|x| console.screen().index_at(x, 3) == preview[x]));
+
+        // the post-render, vblank and pre-render scanlines draw nothing
+        for y in [240, 241, 261, u16::MAX] {
+            assert_eq!(console.render_scanline_preview(y), None);
+        }
+    }
+
+    #[test]
+    fn test_pattern_table_bases() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        assert_eq!(console.pattern_table_bases(), (0x0000, 0x0000));
+
+        // PPUCTRL bit 4 selects the background table, bit 3 the sprite table
+        for (control, bases) in [
+            (0b0001_0000, (0x1000, 0x0000)),
+            (0b0000_1000, (0x0000, 0x1000)),
+            (0b1001_1000, (0x1000, 0x1000)),
+        ] {
+            let state = &mut console.state;
+            state.cpu.write_byte(&mut state.bus, 0x2000, control);
+            assert_eq!(console.pattern_table_bases(), bases);
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_io_page() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        let mut buttons = ButtonState::default();
+        buttons.set(Button::A);
+        console.update_buttons(buttons);
+
+        let state = &mut console.state;
+        let read_oam = |state: &mut ConsoleState, addr: u8| {
+            state.cpu.write_byte(&mut state.bus, 0x2003, addr);
+            state.cpu.read_byte(&state.bus, 0x2004)
+        };
+
+        // $40xx is read through the registers, so port one shifts out the A button
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x01);
+        state.cpu.write_byte(&mut state.bus, 0x4016, 0x00);
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x00);
+        state.cpu.write_byte(&mut state.bus, 0x4014, 0x40);
+        assert_eq!(read_oam(state, 0x16), 0x01);
+        assert_eq!(read_oam(state, 0x17), 0x00);
+        assert_eq!(read_oam(state, 0x00), 0x00);
+        // the DMA read the first button, so the next read is B
+        assert_eq!(state.cpu.read_byte(&state.bus, 0x4016), 0x00);
+
+        // PRG RAM
+        state.cpu.write_byte(&mut state.bus, 0x6010, 0xa5);
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x00);
+        state.cpu.write_byte(&mut state.bus, 0x4014, 0x60);
+        assert_eq!(read_oam(state, 0x10), 0xa5);
+    }
+
+    #[test]
+    fn test_cycle_and_frame_counters() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(1).unwrap();
+
+        let frames = console.frame_count();
+        let cycles = console.cpu_cycles();
+        console.run_frames(10).unwrap();
+
+        assert_eq!(console.frame_count() - frames, 10);
+        // 341 * 262 / 3 CPU cycles per frame with rendering disabled, give or take an instruction
+        let elapsed = console.cpu_cycles().wrapping_sub(cycles);
+        assert!(elapsed.abs_diff(297_807) <= 3, "elapsed {}", elapsed);
+    }
+
+    #[test]
+    fn test_frame_parity() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.run_frames(1).unwrap();
+        let mut parity = console.frame_parity();
+        for _ in 0..4 {
+            console.run_frames(1).unwrap();
+            assert_ne!(console.frame_parity(), parity);
+            parity = console.frame_parity();
+        }
+
+        let state = console.save_state();
+        console.run_frames(1).unwrap();
+        console.load_state(&state).unwrap();
+        assert_eq!(console.frame_parity(), parity);
+    }
+
+    #[test]
+    fn test_cycles_to_dots() {
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        for cycles in [0, 1, 29_780, u32::MAX as u64] {
+            let dots = console.cpu_cycles_to_dots(cycles);
+            assert_eq!(console.dots_to_cpu_cycles(dots), cycles);
+        }
+        assert_eq!(console.dots_to_cpu_cycles(DOTS_PER_FRAME), 29_780);
+        assert_eq!(
+            console.cpu_cycles_to_dots(u64::MAX),
+            u64::MAX.wrapping_mul(DOTS_PER_CPU_CYCLE)
+        );
+        assert_eq!(DOTS_PER_FRAME, 89_342);
+        assert_eq!(CPU_CYCLES_PER_FRAME, 29_780.5);
+
+        // with rendering disabled, every frame is the full length
+        console.run_frames(1).unwrap();
+        let cycles = console.cpu_cycles();
+        console.run_frames(3).unwrap();
+        let dots = console.cpu_cycles_to_dots(console.cpu_cycles() - cycles);
+        assert!(dots.abs_diff(3 * DOTS_PER_FRAME) <= 9, "dots {}", dots);
+    }
+
+    #[test]
+    fn test_debug_render_tile() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+
+        // tile 1: a diagonal in the low plane, the left half in the high plane
+        let low: [u8; 8] = core::array::from_fn(|y| 0x80 >> y);
+        console.debug_write_ppu(0x0010, &low);
+        console.debug_write_ppu(0x0018, &[0xf0; 8]);
+        console.debug_write_ppu(0x2000, &[0x01]);
+        console.debug_write_ppu(0x3f00, &[0x0f, 0x11, 0x22, 0x33]);
+        console.debug_set_scroll(0, 0, 0);
+        console.debug_set_registers(0x00, 0b0000_1010);
+
+        // the first frame starts without the pre-render fetches
+        console.next_screen();
+        let screen = console.next_screen();
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let color = ((x < 4) as usize) << 1 | (x == y) as usize;
+                assert_eq!(
+                    screen.pixels[y][x],
+                    [0x0f, 0x11, 0x22, 0x33][color],
+                    "pixel ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+
+        // the rest of the nametable is tile 0, which is blank
+        assert_eq!(screen.pixels[0][8], 0x0f);
+    }
+
+    // a writer that can still be read after it's handed over to the console
+    #[derive(Clone, Default)]
+    struct SharedLog(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_apply_patches() {
+        // LDA #$00; STA $10; JMP $8004
+        let mut console = test_console(&[0xa9, 0x00, 0x85, 0x10, 0x4c, 0x04, 0x80]);
+        console.apply_patches(&[(0x8001, 0x42), (0x6000, 0x24)]);
+
+        assert_eq!(console.peek(0x8001), 0x42);
+        assert_eq!(console.peek(0x8000), 0xa9);
+        assert_eq!(console.peek(0x6000), 0x24);
+
+        console.step_instruction();
+        console.step_instruction();
+        assert_eq!(console.peek(0x0010), 0x42);
+
+        // the patch survives a reset, and the page is still usable for OAM DMA
+        console.reset();
+        assert_eq!(console.peek(0x8001), 0x42);
+        let state = &mut console.state;
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x00);
+        state.cpu.write_byte(&mut state.bus, 0x4014, 0x80);
+        state.cpu.write_byte(&mut state.bus, 0x2003, 0x01);
+        assert_eq!(state.cpu.read_byte(&state.bus, 0x2004), 0x42);
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut console = test_console(&[0xa9, 0x00, 0x4c, 0x00, 0x80]);
+        let snapshot = console.take_snapshot();
+        console.apply_patches(&[(0x8001, 0x42), (0x6000, 0x24)]);
+        console.apply_patches(&[(0x8002, 0x10)]);
+
+        assert_eq!(
+            console.cheats(),
+            &[
+                CheatEntry {
+                    address: 0x8001,
+                    value: 0x42
+                },
+                CheatEntry {
+                    address: 0x8002,
+                    value: 0x10
+                },
+            ]
+        );
+        assert_eq!(console.peek(0x8001), 0x42);
+        assert_eq!(console.peek(0x8002), 0x10);
+
+        assert_eq!(console.remove_cheat(2), None);
+        assert_eq!(
+            console.remove_cheat(0),
+            Some(CheatEntry {
+                address: 0x8001,
+                value: 0x42
+            })
+        );
+        assert_eq!(console.cheats().len(), 1);
+        assert_eq!(console.peek(0x8001), 0x00);
+        assert_eq!(console.peek(0x8002), 0x10);
+
+        // still on after going back to before the cheats were applied
+        console.restore_snapshot(snapshot);
+        assert_eq!(console.peek(0x8001), 0x00);
+        assert_eq!(console.peek(0x8002), 0x10);
+
+        console.clear_cheats();
+        assert!(console.cheats().is_empty());
+        assert_eq!(console.peek(0x8002), 0x4c);
+    }
+
+    #[test]
+    fn test_rom_write_hook() {
+        // LDA #$42; STA $C123; STA $0200; JMP $8008
+        let mut console = test_console(&[
+            0xa9, 0x42, 0x8d, 0x23, 0xc1, 0x8d, 0x00, 0x02, 0x4c, 0x08, 0x80,
+        ]);
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let hook_writes = writes.clone();
+        console.set_rom_write_hook(Some(Box::new(move |write| {
+            hook_writes.borrow_mut().push(write)
+        })));
+
+        for _ in 0..4 {
+            console.step_instruction();
+        }
+        assert_eq!(
+            *writes.borrow(),
+            [RomWrite {
+                address: 0xc123,
+                value: 0x42,
+                pc: 0x8002,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_illegal_opcode_callback() {
+        // LDA #$42; NOP; NOP (unofficial); SBC #$01 (unofficial); LAX $10; JMP $8008
+        let mut console = test_console(&[
+            0xa9, 0x42, 0xea, 0x1a, 0xeb, 0x01, 0xa7, 0x10, 0x4c, 0x08, 0x80,
+        ]);
+        let executed = Rc::new(RefCell::new(Vec::new()));
+        let callback_executed = executed.clone();
+        console.set_illegal_opcode_callback(Some(Box::new(move |opcode, pc| {
+            callback_executed.borrow_mut().push((opcode, pc))
+        })));
+
+        for _ in 0..5 {
+            console.step_instruction();
+        }
+        assert_eq!(
+            *executed.borrow(),
+            [(0x1a, 0x8003), (0xeb, 0x8004), (0xa7, 0x8006)]
+        );
+
+        // looping on the official JMP
+        for _ in 0..3 {
+            console.step_instruction();
+        }
+        assert_eq!(executed.borrow().len(), 3);
+
+        console.set_illegal_opcode_callback(None);
+        assert!(console.state.bus.illegal_opcode_callback.is_none());
+    }
+
+    #[test]
+    fn test_sprite_zero_stall_diagnostic() {
+        // sprite zero below the picture, background on, then wait for the hit:
+        // LDA #$F0; STA $2004; LDA #mask; STA $2001; BIT $2002; BVC -5
+        let program = |mask: u8| {
+            [
+                0xa9, 0xf0, 0x8d, 0x04, 0x20, 0xa9, mask, 0x8d, 0x01, 0x20, 0x2c, 0x02, 0x20, 0x50,
+                0xfb,
+            ]
+        };
+        let diagnostics = Rc::new(RefCell::new(Vec::new()));
+
+        let mut console = test_console(&program(0x18));
+        let callback_diagnostics = diagnostics.clone();
+        console.set_diagnostic_callback(Some(Box::new(move |diagnostic| {
+            callback_diagnostics.borrow_mut().push(diagnostic)
+        })));
+        console.run_frames(3).unwrap();
+        assert!(!diagnostics.borrow().is_empty());
+        assert!(diagnostics
+            .borrow()
+            .iter()
+            .all(|diagnostic| matches!(diagnostic, Diagnostic::SpriteZeroStall { .. })));
+
+        // with rendering off, there's no hit to wait for
+        diagnostics.borrow_mut().clear();
+        let mut console = test_console(&program(0x00));
+        let callback_diagnostics = diagnostics.clone();
+        console.set_diagnostic_callback(Some(Box::new(move |diagnostic| {
+            callback_diagnostics.borrow_mut().push(diagnostic)
+        })));
+        console.run_frames(3).unwrap();
+        assert!(diagnostics.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_access_log() {
+        // LDA #$42; STA $0200; JMP $8005
+        let mut console = test_console(&[0xa9, 0x42, 0x8d, 0x00, 0x02, 0x4c, 0x05, 0x80]);
+        console.step_instruction();
+        assert!(console.last_instruction_accesses().is_empty());
+
+        console.set_access_logging(true);
+        console.step_instruction();
+        let accesses = console.last_instruction_accesses();
+        let writes: Vec<&MemAccess> = accesses
+            .iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .collect();
+        assert_eq!(
+            writes,
+            [&MemAccess {
+                address: 0x0200,
+                value: 0x42,
+                kind: AccessKind::Write,
+            }]
+        );
+        // the opcode and operand fetches come first
+        assert_eq!(
+            accesses[0],
+            MemAccess {
+                address: 0x8002,
+                value: 0x8d,
+                kind: AccessKind::Read,
+            }
+        );
+
+        // peeking isn't part of an instruction
+        console.peek(0x0200);
+        assert_eq!(console.last_instruction_accesses().len(), accesses.len());
+    }
+
+    #[test]
+    fn test_cpu_trace() {
+        // LDA #$01; STA $10; JMP $8000
+        let mut console = test_console(&[0xa9, 0x01, 0x85, 0x10, 0x4c, 0x00, 0x80]);
+        let log = SharedLog::default();
+
+        console.enable_cpu_trace(Box::new(log.clone()));
+        for _ in 0..3 {
+            console.step_instruction();
+        }
+        console.disable_cpu_trace();
+        console.step_instruction();
+
+        let expected = concat!(
+            "8000  A9 01     LDA #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0\n",
+            "8002  85 10     STA $10 = 00                    A:01 X:00 Y:00 P:24 SP:FD PPU:  0,  6 CYC:2\n",
+            "8004  4C 00 80  JMP $8000                       A:01 X:00 Y:00 P:24 SP:FD PPU:  0, 15 CYC:5\n",
+        );
+        assert_eq!(String::from_utf8(log.0.take()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_cpu_trace_ppu_columns() {
+        // LDA $10; INC $10; JMP $8000
+        let mut console = test_console(&[0xa5, 0x10, 0xe6, 0x10, 0x4c, 0x00, 0x80]);
+        let log = SharedLog::default();
+        console.enable_cpu_trace(Box::new(log.clone()));
+
+        let executed: Vec<u16> = (0..200)
+            .map(|_| console.step_instruction().cycles())
+            .collect();
+
+        let log = String::from_utf8(log.0.take()).unwrap();
+        let positions: Vec<(u32, u32, u64)> = log
+            .lines()
+            .map(|line| {
+                let (_, ppu) = line.split_once("PPU:").unwrap();
+                let (scanline, rest) = ppu.split_once(',').unwrap();
+                let (dot, cycles) = rest.split_once(" CYC:").unwrap();
+                (
+                    scanline.trim().parse().unwrap(),
+                    dot.trim().parse().unwrap(),
+                    cycles.parse().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(positions.len(), executed.len());
+
+        // each line shows the PPU 3 dots per CPU cycle after the previous one, wrapping scanlines
+        for (window, cycles) in positions.windows(2).zip(&executed) {
+            let [(prev_line, prev_dot, prev_cycles), (line, dot, cpu_cycles)] = window else {
+                unreachable!()
+            };
+            assert!(*dot <= 340);
+            assert_eq!(cpu_cycles - prev_cycles, *cycles as u64);
+            assert_eq!(
+                (line * 341 + dot) - (prev_line * 341 + prev_dot),
+                3 * *cycles as u32
+            );
+        }
+
+        // 200 instructions are enough to cross a few scanlines
+        assert!(positions.last().unwrap().0 >= 4);
+    }
+
+    #[test]
+    fn test_chr_banks() {
+        let mut cartridge = test_cartridge(&[0xea]);
+        cartridge.chr = CHR::ROM(Rc::new(vec![[0x00; 0x2000], [0x5a; 0x2000]]));
+        let mut console = Console::new(cartridge::new(cartridge, 3).unwrap());
+
+        // the second bank isn't mapped, but can still be read
+        assert_eq!(console.chr_banks().len(), 2);
+        assert_eq!(console.chr_banks()[1][0x1234], 0x5a);
+        assert!(console.chr_banks_mut().is_none());
+
+        let mut console = test_console(&[0xea]);
+        let snapshot = console.take_snapshot();
+        console.chr_banks_mut().unwrap()[0][0x0010] = 0xa5;
+        assert_eq!(console.chr_banks()[0][0x0010], 0xa5);
+        assert_eq!(console.state.bus.mapper.read(0x0010), 0xa5);
+
+        // CHR RAM is still copy-on-write
+        assert_eq!(snapshot.0.bus.mapper.chr_banks()[0][0x0010], 0x00);
+    }
+
+    #[test]
+    fn test_load_rom_bytes() {
+        let image = |mapper: u8| {
+            // reset vector at $FFFC
+            let mut prg = [0u8; 0x3ffe];
+            prg[0x3ffc..].copy_from_slice(&[0x34, 0x92]);
+            ines_image(&[1, 1, mapper << 4], &prg)
+        };
+
+        let console = Console::load_rom_bytes(&image(0)).unwrap();
+        assert_eq!(console.state.cpu.registers().pc, 0x9234);
+
+        assert_eq!(
+            Console::load_rom_bytes(&image(4)).err(),
+            Some(LoadError::UnsupportedMapper(4))
+        );
+        assert_eq!(
+            Console::load_rom_bytes(&image(0)[..0x1000]).err(),
+            Some(LoadError::InvalidImage)
+        );
+    }
+
+    #[test]
+    fn test_load_and_insert() {
+        // NROM with 1 PRG bank, 1 CHR bank, looping at the reset vector
+        let image = |reset: u16| {
+            let mut prg = [0u8; 0x4000];
+            let offset = reset as usize % 0x4000;
+            let [lo, hi] = reset.to_le_bytes();
+            prg[offset..offset + 3].copy_from_slice(&[0x4c, lo, hi]);
+            prg[0x3ffa..].copy_from_slice(&[lo, hi, lo, hi, lo, hi]);
+            ines_image(&[1, 1, 0], &prg)
+        };
+
+        let mut console = Console::load_rom_bytes(&image(0x8000)).unwrap();
+        console.set_audio_sample_rate(48_000);
+        console.run_frames(2).unwrap();
+        assert_eq!(console.state.cpu.registers().pc, 0x8000);
+
+        assert_eq!(
+            console.load_and_insert(&image(0x9234)[..100]),
+            Err(LoadError::InvalidImage)
+        );
+        assert_eq!(console.state.cpu.registers().pc, 0x8000);
+
+        console.load_and_insert(&image(0x9234)).unwrap();
+        assert_eq!(console.state.cpu.registers().pc, 0x9234);
+        assert_eq!(console.cpu_cycles(), 0);
+        assert_eq!(console.frame_count(), 0);
+        assert_eq!(console.audio_buffer_capacity(), 48_000);
+
+        // nothing to rewind into from the previous game
+        console.run_frames(1).unwrap();
+        assert!(console.rewind_frames(10) <= 1);
+        assert_eq!(console.state.cpu.registers().pc, 0x9234);
+    }
+
+    #[test]
+    fn test_expansion_device() {
+        // NES 2.0 NROM image listing a Zapper in port two as its default expansion device
+        let mut image = ines_image(&[1, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0x08], &[]);
+
+        let mut console = Console::load_rom_bytes(&image).unwrap();
+        let read_ports = |console: &Console| {
+            let state = &console.state;
+            [0x4016, 0x4017].map(|port| state.cpu.read_byte(&state.bus, port) & 0x19)
+        };
+        assert_eq!(read_ports(&console), [0x00, 0x08]);
+
+        console.update_zapper(ZapperState {
+            trigger: true,
+            light: true,
+        });
+        assert_eq!(read_ports(&console), [0x00, 0x10]);
+
+        // a plain iNES image keeps the devices the frontend chose
+        image[7] = 0;
+        image[15] = 0;
+        console.load_and_insert(&image).unwrap();
+        assert_eq!(read_ports(&console), [0x00, 0x10]);
+    }
+
+    #[test]
+    fn test_cartridge_info() {
+        // 2 PRG banks, 1 CHR bank, UxROM
+        let image = ines_image(&[2, 1, 0x20], &[]);
+        let console = Console::load_rom_bytes(&image).unwrap();
+        assert_eq!(
+            console.cartridge_info(),
+            CartridgeInfo {
+                mapper: 2,
+                prg_kb: 32,
+                chr_kb: 8,
+                chr_ram: false,
+            }
+        );
+
+        let console = test_console(&[0x4c, 0x00, 0x80]);
+        assert_eq!(
+            console.cartridge_info(),
+            CartridgeInfo {
+                mapper: 0,
+                prg_kb: 16,
+                chr_kb: 8,
+                chr_ram: true,
+            }
+        );
+    }
+
+    // Read a file from the tests/nes-test-roms submodule, or None when it isn't checked out.
+    // CI checks the submodule out, so a missing file fails there rather than passing silently
+    fn read_test_rom(path: &str) -> Option<Vec<u8>> {
+        let path = std::path::Path::new("tests/nes-test-roms").join(path);
+        match std::fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(err) if std::env::var_os("CI").is_some() => {
+                panic!("can't read {} ({})", path.display(), err)
+            }
+            Err(err) => {
+                eprintln!(
+                    "skipping: can't read {} ({}), run `git submodule update --init`",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_rom_nestest() {
+        let Some(rom) = read_test_rom("other/nestest.nes") else {
+            return;
+        };
+        let console = Console::load_rom_bytes(&rom).unwrap();
+        assert_eq!(console.state.cpu.registers().pc, 0xc004);
+    }
+
+    // Run `rom` from $C000 like nestest's automated mode, tracing up to `max_lines`
+    // instructions and comparing each line with the reference `golden_log`.
+    // The error names the first line that differs.
+    fn run_against_log(rom: &[u8], golden_log: &str, max_lines: usize) -> Result<(), String> {
+        let mut console =
+            Console::load_rom_bytes(rom).map_err(|err| format!("loading the ROM: {}", err))?;
+        let mut screen = Screen::default();
+        let state = &mut console.state;
+
+        // reference logs start 7 CPU cycles, 21 dots, after reset
+        state.cpu.debug_set_pc(0xc000);
+        state.cpu.debug_set_cycles(7);
+        for _ in 0..21 {
+            state.bus.ppu.step(state.bus.mapper.as_mut(), &mut screen);
+        }
+
+        let mut line = Vec::new();
+        for (index, expected) in golden_log.lines().take(max_lines).enumerate() {
+            // emulators logging the reference don't mark interrupts
+            loop {
+                line.clear();
+                state.step(&mut screen, Some(&mut line));
+                if !line.starts_with(b"========") {
+                    break;
+                }
+            }
+
+            let actual = String::from_utf8_lossy(&line);
+            let actual = actual.trim_end();
+            if actual != expected.trim_end() {
+                return Err(format!(
+                    "line {} differs\nexpected: {}\n  actual: {}",
+                    index + 1,
+                    expected,
+                    actual
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nestest_log() {
+        let (Some(rom), Some(golden_log)) = (
+            read_test_rom("other/nestest.nes"),
+            read_test_rom("other/nestest.log"),
+        ) else {
+            return;
+        };
+        let golden_log = String::from_utf8_lossy(&golden_log);
+
+        // every instruction up to the end of the unofficial opcode tests
+        if let Err(divergence) = run_against_log(&rom, &golden_log, 8991) {
+            panic!("{}", divergence);
+        }
+    }
+
+    #[test]
+    fn test_run_against_log() {
+        // LDA #$42; JMP $C000
+        let image = ines_image(&[1, 1, 0], &[0xa9, 0x42, 0x4c, 0x00, 0xc0]);
+
+        let golden_log = "\
+C000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C002  4C 00 C0  JMP $C000                       A:42 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+C000  A9 42     LDA #$42                        A:42 X:00 Y:00 P:24 SP:FD PPU:  0, 36 CYC:12
+";
+        assert_eq!(run_against_log(&image, golden_log, 3), Ok(()));
+
+        let diverging = golden_log.replace("CYC:12", "CYC:13");
+        let error = run_against_log(&image, &diverging, 3).unwrap_err();
+        assert!(error.starts_with("line 3 differs"), "{}", error);
+        assert!(error.ends_with("CYC:12"), "{}", error);
+    }
+
+    #[test]
+    fn test_battery_ram() {
+        let load = |flags6: u8| {
+            let image = ines_image(&[1, 1, flags6], &[]);
+            let (cartridge, mapper) = ines::load_bytes(&image).unwrap();
+            Console::new(cartridge::new(cartridge, mapper).unwrap())
+        };
+
+        let console = load(0b0010);
+        assert!(console.has_battery_ram());
+        assert_eq!(console.prg_ram_size(), 0x2000);
+
+        let console = load(0b0000);
+        assert!(!console.has_battery_ram());
+        assert_eq!(console.prg_ram_size(), 0x2000);
+    }
+
+    #[test]
+    fn test_irq_after_cli_sei() {
+        // 8000: RTI (the IRQ handler)
+        // 8010: CLI; SEI; NOP; JMP $8013
+        let mut program = [0u8; 0x16];
+        program[0] = 0x40;
+        program[0x10..].copy_from_slice(&[0x58, 0x78, 0xea, 0x4c, 0x13, 0x80]);
+        let mut console = test_console(&program);
+        console.debug_set_pc(0x8010);
+        console.state.bus.apu.frame_irq.set(true);
+
+        let pcs: Vec<Option<u16>> = (0..5)
+            .map(|_| match console.step_instruction() {
+                ExecutedInstruction::Instruction { pc, .. } => Some(pc),
+                ExecutedInstruction::Interrupt { .. } => None,
+            })
+            .collect();
+
+        // CLI only lets the IRQ through after the next instruction, and SEI polls before
+        // setting the flag, so the IRQ lands between SEI and NOP. The pushed flags have I set,
+        // so RTI returns with interrupts disabled.
+        assert_eq!(
+            pcs,
+            [Some(0x8010), Some(0x8011), None, Some(0x8000), Some(0x8012)]
+        );
+        assert!(console.state.bus.apu.frame_irq.get());
+    }
+
+    #[test]
+    fn test_nop_absolute_x() {
+        // LDX #$FF; NOP $3F16,X; NOP $3F16
+        let mut console = test_console(&[0xa2, 0xff, 0x1c, 0x16, 0x3f, 0x0c, 0x16, 0x3f]);
+        console.step_instruction();
+
+        // interrupts are disabled after reset, so the flag stays up until $4015 is read
+        console.state.bus.apu.frame_irq.set(true);
+
+        // crossing into page $40 costs a cycle, and the dummy read of $4015 acknowledges the IRQ
+        assert_eq!(console.step_instruction().cycles(), 5);
+        assert!(!console.state.bus.apu.frame_irq.get());
+
+        // NOP abs has no penalty
+        assert_eq!(console.step_instruction().cycles(), 4);
+    }
+
+    #[test]
+    fn test_zero_page_and_stack_wrapping() {
+        // LDX #$01; LDA $FF,X; LDX #$00; LDA ($FF,X); TXS; PHA; PHA; JMP ($02FF)
+        let mut console = test_console(&[
+            0xa2, 0x01, 0xb5, 0xff, 0xa2, 0x00, 0xa1, 0xff, 0x9a, 0x48, 0x48, 0x6c, 0xff, 0x02,
+        ]);
+        let state = &mut console.state;
+        for (addr, data) in [
+            (0x0000, 0x03),
+            (0x00ff, 0x10),
+            (0x0310, 0x42),
+            (0x0200, 0x80),
+            (0x02ff, 0x0b),
+            (0x0300, 0x90),
+        ] {
+            state.cpu.write_byte(&mut state.bus, addr, data);
+        }
+
+        // $FF,X reads $0000
+        console.step_instruction();
+        console.step_instruction();
+        assert_eq!(console.state.cpu.registers().a, 0x03);
+
+        // the pointer at $FF has its high byte at $00: $0310
+        console.step_instruction();
+        console.step_instruction();
+        assert_eq!(console.state.cpu.registers().a, 0x42);
+
+        // pushing with SP = 0 writes $0100, then wraps to $01FF
+        console.step_instruction();
+        console.step_instruction();
+        console.step_instruction();
+        assert_eq!(console.peek(0x0100), 0x42);
+        assert_eq!(console.peek(0x01ff), 0x42);
+        assert_eq!(console.state.cpu.registers().sp, 0xfe);
+
+        // the high byte of the pointer at $02FF is at $0200, not $0300
+        console.step_instruction();
+        assert_eq!(console.state.cpu.registers().pc, 0x800b);
+    }
+
+    #[test]
+    fn test_indexed_store_cycles() {
+        // LDX #$01; LDY #$01; STA $0010,X; STA $02FF,X; STA $0010,Y; STA $02FF,Y;
+        // STA ($20),Y with $20 = $0010; STA ($22),Y with $22 = $02FF
+        let mut console = test_console(&[
+            0xa2, 0x01, 0xa0, 0x01, 0x9d, 0x10, 0x00, 0x9d, 0xff, 0x02, 0x99, 0x10, 0x00, 0x99,
+            0xff, 0x02, 0x91, 0x20, 0x91, 0x22,
+        ]);
+        let state = &mut console.state;
+        for (addr, data) in [(0x20, 0x10), (0x21, 0x00), (0x22, 0xff), (0x23, 0x02)] {
+            state.cpu.write_byte(&mut state.bus, addr, data);
+        }
+        console.step_instruction();
+        console.step_instruction();
+
+        // stores always pay for the dummy read, whether or not the page is crossed
+        let cycles: Vec<u16> = (0..6)
+            .map(|_| console.step_instruction().cycles())
+            .collect();
+        assert_eq!(cycles, [5, 5, 5, 5, 6, 6]);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn test_run_frames_dumping() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21]);
+        console.debug_set_registers(0, 0b0000_1010);
+
+        let out_dir = std::env::temp_dir().join("nes-rs-frame-dump");
+        let _ = std::fs::remove_dir_all(&out_dir);
+        console.run_frames_dumping(3, &out_dir).unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&out_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["frame_000001.png", "frame_000002.png", "frame_000003.png"]
+        );
+
+        let file = std::fs::File::open(out_dir.join("frame_000003.png")).unwrap();
+        let mut reader = png::Decoder::new(file).read_info().unwrap();
+        let mut rgb = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgb).unwrap();
+        assert_eq!((info.width, info.height), (256, 240));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+
+        let mut expected = vec![0u8; 256 * 240 * 3];
+        console.screen().write_rgb(&mut expected).unwrap();
+        assert_eq!(rgb, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn test_save_frame_indexed_png() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21, 0x16]);
+        console.debug_write_ppu(0x2000, &[0x01]);
+        console.debug_write_ppu(0x0010, &[0xff; 8]);
+        console.debug_set_registers(0, 0b0000_1010);
+        console.run_frames(2).unwrap();
+
+        let path = std::env::temp_dir().join("nes-rs-indexed-frame.png");
+        console.save_frame_indexed_png(&path).unwrap();
+
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let palette = reader.info().palette.as_ref().unwrap().to_vec();
+        assert_eq!(palette.len(), 64 * 3);
+        assert_eq!(palette[0x16 * 3..0x17 * 3], [0xb5, 0x31, 0x20]);
+
+        let mut indexes = vec![0u8; reader.output_buffer_size()];
+        reader.next_frame(&mut indexes).unwrap();
+        let expected: Vec<u8> = console
+            .screen()
+            .pixels
+            .iter()
+            .flatten()
+            .map(|pixel| *pixel as u8)
+            .collect();
+        assert_eq!(indexes, expected);
+        // tile 1 in the top left corner
+        assert_eq!(
+            indexes[..9],
+            [0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x21]
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        // $8000: LDA #$42; STA $10; JMP $8007; $8007: JMP $8007
+        let mut console =
+            test_console(&[0xa9, 0x42, 0x85, 0x10, 0x4c, 0x07, 0x80, 0x4c, 0x07, 0x80]);
+        console.run_until_pc(0x8007, 100);
+        let sp = console.state.cpu.registers().sp;
+
+        console.reset();
+        let registers = console.state.cpu.registers();
+        assert_eq!(registers.pc, 0x8000);
+        assert_eq!(registers.a, 0x42);
+        assert_eq!(registers.sp, sp.wrapping_sub(3));
+        assert_eq!(console.peek(0x0010), 0x42);
+    }
+
+    #[test]
+    fn test_screen() {
+        // JMP $8000
+        let mut console = test_console(&[0x4c, 0x00, 0x80]);
+        console.debug_write_ppu(0x3f00, &[0x21]);
+        let rendered = console.next_screen().pixels;
+        let cycles = console.cpu_cycles();
+
+        assert_eq!(console.screen().pixels, rendered);
+        assert_eq!(console.screen().pixels, rendered);
+        assert_eq!(console.cpu_cycles(), cycles);
+    }
 }
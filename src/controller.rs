@@ -1,4 +1,8 @@
-use std::cell::Cell;
+use core::cell::Cell;
+
+use dyn_clone::DynClone;
+
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
 
 pub enum Button {
     A = 0,
@@ -24,36 +28,95 @@ impl ButtonState {
     }
 }
 
+/// When the buttons passed to `Console::update_buttons` reach the controller
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InputPollTiming {
+    /// Right away, so a game polling later in the same frame sees them
+    #[default]
+    Immediate,
+    /// Once the current frame ends, so the whole of every frame sees the same buttons
+    /// wherever the game polls, like a frontend with a frame of input latency
+    NextFrame,
+}
+
+pub enum Port {
+    One = 0,
+    Two = 1,
+}
+
+/// A device plugged into one of the controller ports
+pub trait InputDevice: DynClone {
+    /// A CPU write to $4016, which is seen by both ports. The whole byte is passed on:
+    /// bit 0 is the strobe, and bits 1-2 drive the Famicom expansion port outputs
+    fn write_strobe(&mut self, data: u8);
+    /// A CPU read from $4016 (port one) or $4017 (port two)
+    fn read(&self) -> u8;
+    /// The buttons held on the frontend, for devices that have buttons
+    fn update_buttons(&mut self, _state: ButtonState) {}
+    /// The trigger and light sensor of a light gun, for Zappers
+    fn update_zapper(&mut self, _state: ZapperState) {}
+    /// Append the device's internal state to a save state
+    fn save_state(&self, _state: &mut StateWriter) {}
+    /// Restore the internal state written by `save_state`
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), LoadStateError> {
+        Ok(())
+    }
+}
+
+dyn_clone::clone_trait_object!(InputDevice);
+
+/// What a standard controller reports once all 8 buttons have been read
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ControllerMode {
+    /// Nintendo controllers return 1 for every read past the 8 buttons
+    #[default]
+    Official,
+    /// Some third party controllers, like the U-Force, return 0 instead
+    ThirdParty,
+}
+
 #[derive(Clone, Default)]
-pub(crate) struct Controller {
+pub struct Controller {
     pub(crate) button_state: ButtonState,
     strobe: bool,
+    mode: ControllerMode,
 
     index: Cell<u8>,
 }
 
 impl Controller {
-    pub(crate) fn update_buttons(&mut self, state: ButtonState) {
+    pub fn new(mode: ControllerMode) -> Self {
+        Controller {
+            mode,
+            ..Default::default()
+        }
+    }
+}
+
+impl InputDevice for Controller {
+    fn update_buttons(&mut self, state: ButtonState) {
         self.button_state = state;
     }
 
-    pub(crate) fn read(&self) -> u8 {
+    fn read(&self) -> u8 {
         // https://www.nesdev.org/wiki/Standard_controller
         // Each read reports one bit at a time through D0. The first 8 reads will indicate which buttons
         // or directions are pressed (1 if pressed, 0 if not pressed). All subsequent reads will return 1 on official
         // Nintendo brand controllers but may return 0 on third party controllers such as the U-Force.
-        let mut result: u8 = 0;
         let index = self.index.get();
 
         if index < 8 {
-            result = (self.button_state.0 >> index) & 1;
             self.index.set(if !self.strobe { index + 1 } else { index });
+            (self.button_state.0 >> index) & 1
+        } else {
+            match self.mode {
+                ControllerMode::Official => 1,
+                ControllerMode::ThirdParty => 0,
+            }
         }
-
-        result
     }
 
-    pub(crate) fn write(&mut self, data: u8) {
+    fn write_strobe(&mut self, data: u8) {
         // https://www.nesdev.org/wiki/Standard_controller
         // 7  bit  0
         // ---- ----
@@ -66,4 +129,108 @@ impl Controller {
             self.index.set(0);
         }
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.button_state.0);
+        state.write_bool(self.strobe);
+        state.write_u8(self.index.get());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.button_state = ButtonState(state.read_u8()?);
+        self.strobe = state.read_bool()?;
+        self.index.set(state.read_u8()?);
+        Ok(())
+    }
+}
+
+/// What a Zapper sees. The frontend decides whether the gun points at light, e.g. from the
+/// brightness of the pixels under the mouse while the frame is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZapperState {
+    pub trigger: bool,
+    pub light: bool,
+}
+
+/// The NES Zapper light gun, read through bits 3 and 4 of its port
+#[derive(Clone, Default)]
+pub struct Zapper {
+    state: ZapperState,
+}
+
+impl InputDevice for Zapper {
+    fn update_zapper(&mut self, state: ZapperState) {
+        self.state = state;
+    }
+
+    fn read(&self) -> u8 {
+        // https://www.nesdev.org/wiki/Zapper
+        // 7  bit  0
+        // ---- ----
+        // xxxT WxxS
+        //    | |  |
+        //    | |  +- Serial data (Vs.)
+        //    | +---- Light sense (0: detected; 1: not detected)
+        //    +------ Trigger (0: released or fully pulled; 1: half-pulled)
+        (!self.state.light as u8) << 3 | (self.state.trigger as u8) << 4
+    }
+
+    fn write_strobe(&mut self, _data: u8) {}
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.state.trigger);
+        state.write_bool(self.state.light);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.state.trigger = state.read_bool()?;
+        self.state.light = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Button, ButtonState, Controller, ControllerMode, InputDevice, Zapper, ZapperState,
+    };
+
+    fn read_bits(mode: ControllerMode) -> Vec<u8> {
+        let mut controller = Controller::new(mode);
+        let mut buttons = ButtonState::default();
+        buttons.set(Button::A);
+        buttons.set(Button::Start);
+        controller.update_buttons(buttons);
+
+        controller.write_strobe(1);
+        controller.write_strobe(0);
+        (0..12).map(|_| controller.read()).collect()
+    }
+
+    #[test]
+    fn test_controller_mode() {
+        // A, B, Select, Start, Up, Down, Left, Right
+        let buttons = [1, 0, 0, 1, 0, 0, 0, 0];
+
+        assert_eq!(
+            read_bits(ControllerMode::Official),
+            [&buttons[..], &[1; 4]].concat()
+        );
+        assert_eq!(
+            read_bits(ControllerMode::ThirdParty),
+            [&buttons[..], &[0; 4]].concat()
+        );
+    }
+
+    #[test]
+    fn test_zapper() {
+        let mut zapper = Zapper::default();
+        assert_eq!(zapper.read(), 0b0000_1000);
+
+        zapper.update_zapper(ZapperState {
+            trigger: true,
+            light: true,
+        });
+        assert_eq!(zapper.read(), 0b0001_0000);
+    }
 }
@@ -1,6 +1,13 @@
+// instruction traces go to any io::Write with std, and to a fmt::Write without it
+#[cfg(not(feature = "std"))]
+pub(crate) use core::fmt::Write as LogWrite;
+#[cfg(feature = "std")]
+pub(crate) use std::io::Write as LogWrite;
+
 use crate::bus::MemoryBus;
 use crate::cartridge::Mapper;
 use crate::instructions::*;
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
 
 enum StatusFlags {
     C = 0, // Carry Flag
@@ -13,6 +20,82 @@ enum StatusFlags {
     N = 7, // Negative Flag
 }
 
+/// A copy of the CPU registers
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub sp: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    NMI,
+    IRQ,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A read or write the CPU made on the bus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemAccess {
+    pub address: u16,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+/// A CPU write to PRG ROM that the cartridge ignores, usually a bug in the game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RomWrite {
+    pub address: u16,
+    pub value: u8,
+    // the instruction that wrote it
+    pub pc: u16,
+}
+
+/// The result of a single CPU step, with the registers after it completed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecutedInstruction {
+    Instruction {
+        pc: u16,
+        opcode: Opcode,
+        addressing_mode: AddressingMode,
+        // effective address of the operand, if the addressing mode has one
+        operand: Option<u16>,
+        registers: Registers,
+        cycles: u16,
+    },
+    Interrupt {
+        interrupt: Interrupt,
+        registers: Registers,
+        cycles: u16,
+    },
+}
+
+impl ExecutedInstruction {
+    pub fn cycles(&self) -> u16 {
+        match self {
+            ExecutedInstruction::Instruction { cycles, .. } => *cycles,
+            ExecutedInstruction::Interrupt { cycles, .. } => *cycles,
+        }
+    }
+
+    // cycles the CPU was halted for DMA while the instruction ran
+    pub(crate) fn add_stall_cycles(&mut self, stall: u16) {
+        match self {
+            ExecutedInstruction::Instruction { cycles, .. } => *cycles += stall,
+            ExecutedInstruction::Interrupt { cycles, .. } => *cycles += stall,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct CPU {
     cycles: u64,
@@ -23,6 +106,24 @@ pub(crate) struct CPU {
     status: u8,
     sp: u8,
     pub(crate) ram: [u8; 0x800],
+    // where the instruction being executed started, for diagnostics. not saved
+    instruction_pc: u16,
+    // the I flag as the IRQ poll of the last instruction saw it. CLI, SEI and PLP change
+    // the flag after the poll, so they take effect an instruction late. not saved
+    poll_interrupt_disable: bool,
+    // the IRQ line was asserted when the last instruction polled it
+    irq_pending: bool,
+    // the OAM DMA started by the last instruction, for the DMC fetches landing in it.
+    // only tracked with strict accuracy. not saved
+    oam_dma: Option<OamDma>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct OamDma {
+    // the CPU cycle of its last put
+    end: u64,
+    // the cycles it halts the CPU for
+    length: u64,
 }
 
 impl Default for CPU {
@@ -36,10 +137,35 @@ impl Default for CPU {
             status: Default::default(),
             sp: Default::default(),
             ram: [0; 0x800],
+            instruction_pc: Default::default(),
+            poll_interrupt_disable: true,
+            irq_pending: false,
+            oam_dma: None,
         }
     }
 }
 
+fn make_address(lo: u8, hi: u8) -> u16 {
+    u16::from_le_bytes([lo, hi])
+}
+
+// zero page addressing never leaves page 0: $FF,X with X = 1 is $0000, not $0100
+fn zp_wrap(addr: u16) -> u16 {
+    addr & 0x00ff
+}
+
+// the next address without carrying into the page, so a pointer at $xxFF takes its
+// high byte from $xx00. this is the JMP ($xxFF) bug, and zero page pointers work the same
+fn page_wrap_next(addr: u16) -> u16 {
+    let [offset, page] = addr.to_le_bytes();
+    make_address(offset.wrapping_add(1), page)
+}
+
+// the stack is page 1
+fn stack_address(sp: u8) -> u16 {
+    make_address(sp, 0x01)
+}
+
 fn crosses_page_boundary(a: u16, b: u16) -> bool {
     let [_, a_page] = a.to_le_bytes();
     let [_, b_page] = b.to_le_bytes();
@@ -66,6 +192,72 @@ impl CPU {
         self.write_byte(bus, 0x4017, 0x40);
     }
 
+    // the reset button: unlike power-up, registers and RAM are kept
+    pub(crate) fn soft_reset(&mut self, bus: &mut MemoryBus) {
+        // https://www.nesdev.org/wiki/CPU_power_up_state#After_reset
+        self.sp = self.sp.wrapping_sub(3);
+        self.write_status_bit(StatusFlags::I, true);
+        self.pc = self.read_address(bus, 0xfffc);
+
+        // silence the APU
+        self.write_byte(bus, 0x4015, 0x00);
+    }
+
+    pub(crate) fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    pub(crate) fn debug_set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    pub(crate) fn debug_set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn debug_set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub(crate) fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+            sp: self.sp,
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u64(self.cycles);
+        state.write_u16(self.pc);
+        state.write_u8(self.a);
+        state.write_u8(self.x);
+        state.write_u8(self.y);
+        state.write_u8(self.status);
+        state.write_u8(self.sp);
+        state.write_bytes(&self.ram);
+        state.write_bool(self.irq_pending);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.cycles = state.read_u64()?;
+        self.pc = state.read_u16()?;
+        self.a = state.read_u8()?;
+        self.x = state.read_u8()?;
+        self.y = state.read_u8()?;
+        self.status = state.read_u8()?;
+        self.sp = state.read_u8()?;
+        state.read_bytes(&mut self.ram)?;
+        self.irq_pending = state.read_bool()?;
+        Ok(())
+    }
+
     fn check_status_bit(&self, bit: StatusFlags) -> bool {
         let mask = 1 << (bit as u8);
         self.status & mask != 0
@@ -88,11 +280,23 @@ impl CPU {
         self.set_nz(value as u8);
     }
 
+    // https://www.nesdev.org/wiki/CPU_interrupts#Detailed_interrupt_behavior
+    // the IRQ line is polled at the end of the second to last cycle of each instruction,
+    // which is when the console calls this. The interrupt is taken before the next one.
+    pub(crate) fn poll_irq(&mut self, irq_line: bool) {
+        self.irq_pending = irq_line && !self.poll_interrupt_disable;
+    }
+
     pub(crate) fn step(
         &mut self,
         bus: &mut MemoryBus,
-        log: Option<&mut dyn std::io::Write>,
-    ) -> u16 {
+        log: Option<&mut (dyn LogWrite + '_)>,
+    ) -> ExecutedInstruction {
+        let irq_pending = core::mem::take(&mut self.irq_pending);
+        self.oam_dma = None;
+        // interrupt sequences set the I flag before their poll
+        self.poll_interrupt_disable = true;
+
         // NMI takes the highest priority
         if bus.ppu.read_nmi_line() {
             if let Some(log) = log {
@@ -104,17 +308,41 @@ impl CPU {
             self.pc = self.read_address(bus, 0xFFFA);
             self.write_status_bit(StatusFlags::I, true);
             self.cycles = self.cycles.wrapping_add(7);
-            return 7;
+            return ExecutedInstruction::Interrupt {
+                interrupt: Interrupt::NMI,
+                registers: self.registers(),
+                cycles: 7,
+            };
+        }
+
+        // the APU frame counter holds the IRQ line low until acknowledged
+        if irq_pending {
+            if let Some(log) = log {
+                writeln!(log, "======== IRQ ========").unwrap();
+            }
+
+            self.push_address(bus, self.pc);
+            self.push_byte(bus, self.status | (1 << StatusFlags::U as u8));
+            self.pc = self.read_address(bus, 0xFFFE);
+            self.write_status_bit(StatusFlags::I, true);
+            self.cycles = self.cycles.wrapping_add(7);
+            return ExecutedInstruction::Interrupt {
+                interrupt: Interrupt::IRQ,
+                registers: self.registers(),
+                cycles: 7,
+            };
         }
 
         let pre_cycles = self.cycles;
+        let pc = self.pc;
+        self.instruction_pc = pc;
 
         // decode the instrucation @ PC
         let instr = self.decode(bus, self.pc);
 
         if let Some(writer) = log {
             self.debug_instruction(bus, writer, &instr);
-            writer.write(b"\n").unwrap();
+            writeln!(writer).unwrap();
         }
 
         self.pc = self.pc.wrapping_add(instr.width as u16);
@@ -123,9 +351,28 @@ impl CPU {
             .wrapping_add(instr.extended_opcode.min_cycles as u64)
             .wrapping_add(if instr.page_boundary_hit { 1 } else { 0 });
 
+        if !instr.extended_opcode.official {
+            if let Some(callback) = &bus.illegal_opcode_callback {
+                (callback.borrow_mut())(instr.opcode_byte, pc);
+            }
+        }
+
+        let interrupt_disable = self.check_status_bit(StatusFlags::I);
         self.dispatch(bus, instr.extended_opcode.opcode, instr.final_address);
 
-        self.cycles.wrapping_sub(pre_cycles) as u16
+        self.poll_interrupt_disable = match instr.extended_opcode.opcode {
+            Opcode::CLI | Opcode::SEI | Opcode::PLP => interrupt_disable,
+            _ => self.check_status_bit(StatusFlags::I),
+        };
+
+        ExecutedInstruction::Instruction {
+            pc,
+            opcode: instr.extended_opcode.opcode,
+            addressing_mode: instr.extended_opcode.addressing_mode,
+            operand: instr.final_address,
+            registers: self.registers(),
+            cycles: self.cycles.wrapping_sub(pre_cycles) as u16,
+        }
     }
 
     fn branch_on_flag(&mut self, flag: StatusFlags, branch_status: bool, new_pc: u16) {
@@ -350,7 +597,11 @@ impl CPU {
                 self.write_byte(bus, addr, wide as u8);
                 self.set_cnz(wide);
             }
-            (Opcode::NOP, _) => {
+            (Opcode::NOP, Some(addr)) => {
+                // the unofficial NOPs with an operand still read it, which matters for I/O registers
+                self.read_byte(bus, addr);
+            }
+            (Opcode::NOP, None) => {
                 // https://www.nesdev.org/obelisk-6502-guide/reference.html#NOP
             }
             (Opcode::ORA, Some(addr)) => {
@@ -480,6 +731,10 @@ impl CPU {
                 // https://www.nesdev.org/obelisk-6502-guide/reference.html#STA
                 self.write_byte(bus, addr, self.a);
             }
+            (Opcode::STP, None) => {
+                // jams the CPU, which keeps fetching the same opcode until reset
+                self.pc = self.pc.wrapping_sub(1);
+            }
             (Opcode::STP, Some(addr)) => {
                 // https://www.nesdev.org/obelisk-6502-guide/reference.html#STP
                 self.write_byte(bus, addr, self.status);
@@ -528,16 +783,31 @@ impl CPU {
 
     pub(crate) fn read_byte(&self, bus: &MemoryBus, addr: u16) -> u8 {
         // https://www.nesdev.org/wiki/CPU_memory_map
-        match addr {
+        let data = match addr {
             0x0000..=0x1fff => self.ram[addr as usize % self.ram.len()],
             0x2000..=0x3fff => bus.ppu.read_register(bus.mapper.as_ref(), addr), // PPU
             0x4000..=0x4013 => 0,                                                // APU
             0x4014 => 0,                                                         // DMA
-            0x4016 => bus.controller.read(),                                     // controller 1
-            0x4017 => 0,                                                         // controller 2
+            0x4015 => bus.apu.read_status(),                                     // APU status
+            0x4016 => self.read_port(bus, 0),                                    // controller 1
+            0x4017 => self.read_port(bus, 1),                                    // controller 2
             0x4018..=0x401F => 0, // disabled test mode
             _ => bus.mapper.read(addr),
+        };
+
+        bus.log_access(addr, data, AccessKind::Read);
+        data
+    }
+
+    fn read_port(&self, bus: &MemoryBus, port: usize) -> u8 {
+        // https://www.nesdev.org/wiki/DMA#Register_conflicts
+        // a DMC fetch halting the CPU on the read makes the CPU read the port again once it's
+        // done, which the controller sees as one more read, and a bit is lost. reads of operands
+        // are the last cycle of an instruction, where self.cycles already is
+        if bus.dmc_dma_cycle == Some(self.cycles) {
+            bus.ports[port].read();
         }
+        bus.ports[port].read()
     }
 
     fn read_page<'a>(&'a self, mapper: &'a dyn Mapper, page: u8) -> Option<&'a [u8; 256]> {
@@ -552,42 +822,109 @@ impl CPU {
         let lo = self.read_byte(bus, addr);
         let hi = self.read_byte(bus, addr.wrapping_add(1));
 
-        u16::from_le_bytes([lo, hi])
+        make_address(lo, hi)
     }
 
     fn read_address_indirect(&self, bus: &MemoryBus, addr: u16) -> u16 {
-        let [offset, page] = addr.to_le_bytes();
         let lo = self.read_byte(bus, addr);
-        let hi = self.read_byte(bus, u16::from_le_bytes([offset.wrapping_add(1), page]));
+        let hi = self.read_byte(bus, page_wrap_next(addr));
 
-        u16::from_le_bytes([lo, hi])
+        make_address(lo, hi)
     }
 
     pub(crate) fn write_byte(&mut self, bus: &mut MemoryBus, addr: u16, data: u8) {
+        bus.log_access(addr, data, AccessKind::Write);
+
         // https://www.nesdev.org/wiki/CPU_memory_map
         match addr {
             0x0000..=0x1fff => self.ram[addr as usize % self.ram.len()] = data,
             0x2000..=0x3fff => bus.ppu.write_register(bus.mapper.as_mut(), addr, data), // PPU
-            0x4000..=0x4013 => {}                                                       // APU
+            0x4000..=0x4013 | 0x4015 => bus.apu.write_register(addr, data),             // APU
             0x4014 => {
-                let page = self.read_page(bus.mapper.as_ref(), data);
+                // RAM and ROM pages are copied in one go. anything else, like the I/O registers
+                // or PRG RAM, is read an address at a time, side effects included
+                let mut io_page = [0u8; 256];
+                let page = match self.read_page(bus.mapper.as_ref(), data) {
+                    Some(page) => page,
+                    None => {
+                        for (offset, byte) in io_page.iter_mut().enumerate() {
+                            *byte = self.read_byte(bus, u16::from_be_bytes([data, offset as u8]));
+                        }
+                        &io_page
+                    }
+                };
                 bus.ppu.write_dma(page);
+
+                // https://www.nesdev.org/wiki/DMA#OAM_DMA
+                // the CPU is halted for 513 cycles, plus one to align to a get cycle
+                let length = 513 + self.cycles % 2;
+                self.cycles = self.cycles.wrapping_add(length);
+                if bus.ppu.strict_accuracy() {
+                    self.oam_dma = Some(OamDma {
+                        end: self.cycles,
+                        length,
+                    });
+                }
             } // DMA
-            0x4016 => bus.controller.write(data), // controller 1
-            0x4017 => {}                          // controller 2
-            0x4018..=0x401F => {}                 // disabled test mode
-            _ => bus.mapper.write(addr, data),
+            0x4016 => bus
+                .ports
+                .iter_mut()
+                .for_each(|port| port.write_strobe(data)), // controllers
+            0x4017 => bus.apu.write_register(addr, data), // APU frame counter
+            0x4018..=0x401F => {}                         // disabled test mode
+            _ => {
+                if let Some(hook) = &bus.rom_write_hook {
+                    if addr >= 0x8000 && !bus.mapper.is_register_write(addr) {
+                        hook(RomWrite {
+                            address: addr,
+                            value: data,
+                            pc: self.instruction_pc,
+                        });
+                    }
+                }
+                bus.mapper.write(addr, data)
+            }
         };
     }
 
+    // https://www.nesdev.org/wiki/DMA#DMC_DMA
+    // the CPU is halted for a halt cycle, a dummy cycle, then the get cycle of the fetch,
+    // plus one to align to a get cycle. the DMA waits for the CPU to finish writes.
+    // `cycle` is the CPU cycle the DMC asked for `data` on
+    pub(crate) fn dmc_dma_stall(&mut self, bus: &mut MemoryBus, cycle: u64, data: u8) -> u16 {
+        let stall = match &mut self.oam_dma {
+            // https://www.nesdev.org/wiki/DMA#DMC_DMA_during_OAM_DMA
+            // the CPU is already halted, so the fetch only takes a get cycle of the OAM DMA and
+            // one more for it to realign. taking the second to last get, that's a single cycle,
+            // and taking the last one, the DMC DMA runs on its own after it, for three. the
+            // last put then writes the sample byte left on the data bus to OAM
+            Some(dma) if (dma.end - dma.length + 1..=dma.end).contains(&cycle) => {
+                let stall = match dma.end - cycle {
+                    1 => {
+                        bus.ppu.corrupt_dma_last_byte(data);
+                        3
+                    }
+                    3 => 1,
+                    _ => 2,
+                };
+                // a later fetch lands in the delayed rest of the OAM DMA
+                dma.end += stall;
+                stall as u16
+            }
+            _ => 3 + (self.cycles % 2) as u16,
+        };
+        self.cycles = self.cycles.wrapping_add(stall as u64);
+        stall
+    }
+
     fn push_byte(&mut self, bus: &mut MemoryBus, data: u8) {
-        self.write_byte(bus, u16::from_le_bytes([self.sp, 0x1]), data);
+        self.write_byte(bus, stack_address(self.sp), data);
         self.sp = self.sp.wrapping_sub(1);
     }
 
     fn pull_byte(&mut self, bus: &mut MemoryBus) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        self.read_byte(bus, u16::from_le_bytes([self.sp, 0x1]))
+        self.read_byte(bus, stack_address(self.sp))
     }
 
     fn push_address(&mut self, bus: &mut MemoryBus, addr: u16) {
@@ -600,7 +937,7 @@ impl CPU {
         let lo = self.pull_byte(bus);
         let hi = self.pull_byte(bus);
 
-        u16::from_le_bytes([lo, hi])
+        make_address(lo, hi)
     }
 
     fn decode(&self, bus: &MemoryBus, addr: u16) -> DecodedInstruction {
@@ -612,6 +949,7 @@ impl CPU {
             AddressingMode::Absolute => {
                 let address = self.read_address(bus, operand_addr);
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::Absolute { address: address },
                     width: 3,
@@ -620,6 +958,7 @@ impl CPU {
                 }
             }
             AddressingMode::Implied => DecodedInstruction {
+                opcode_byte: opcode,
                 extended_opcode,
                 address_info: AddressInfo::Implied,
                 width: 1,
@@ -627,6 +966,7 @@ impl CPU {
                 final_address: None,
             },
             AddressingMode::Accumulator => DecodedInstruction {
+                opcode_byte: opcode,
                 extended_opcode,
                 address_info: AddressInfo::Accumulator,
                 width: 1,
@@ -638,6 +978,7 @@ impl CPU {
                 let address = indirect.wrapping_add(self.x as u16);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::AbsoluteIndexedX { indirect, address },
                     width: 3,
@@ -651,6 +992,7 @@ impl CPU {
                 let address = indirect.wrapping_add(self.y as u16);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::AbsoluteIndexedY { indirect, address },
                     width: 3,
@@ -662,6 +1004,7 @@ impl CPU {
             AddressingMode::Immediate => {
                 let address = operand_addr;
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::Immediate { address },
                     width: 2,
@@ -671,10 +1014,11 @@ impl CPU {
             }
             AddressingMode::IndexedIndirect => {
                 let offset = self.read_byte(bus, operand_addr);
-                let indirect = offset.wrapping_add(self.x) as u16;
+                let indirect = zp_wrap(offset as u16 + self.x as u16);
                 let address = self.read_address_indirect(bus, indirect);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::IndexedIndirect {
                         offset,
@@ -691,6 +1035,7 @@ impl CPU {
                 let address = self.read_address_indirect(bus, indirect);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::Indirect { indirect, address },
                     width: 3,
@@ -704,6 +1049,7 @@ impl CPU {
                 let address = indirect.wrapping_add(self.y as u16);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::IndirectIndexed {
                         offset,
@@ -726,6 +1072,7 @@ impl CPU {
                 };
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::Relative { offset, address },
                     width: 2,
@@ -736,6 +1083,7 @@ impl CPU {
             AddressingMode::ZeroPage => {
                 let address = self.read_byte(bus, operand_addr);
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::ZeroPage { address: address },
                     width: 2,
@@ -745,9 +1093,10 @@ impl CPU {
             }
             AddressingMode::ZeroPageIndexedX => {
                 let offset = self.read_byte(bus, operand_addr);
-                let address = offset.wrapping_add(self.x) as u16;
+                let address = zp_wrap(offset as u16 + self.x as u16);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::ZeroPageIndexedX { offset, address },
                     width: 2,
@@ -757,9 +1106,10 @@ impl CPU {
             }
             AddressingMode::ZeroPageIndexedY => {
                 let offset = self.read_byte(bus, operand_addr);
-                let address = offset.wrapping_add(self.y) as u16;
+                let address = zp_wrap(offset as u16 + self.y as u16);
 
                 DecodedInstruction {
+                    opcode_byte: opcode,
                     extended_opcode,
                     address_info: AddressInfo::ZeroPageIndexedY { offset, address },
                     width: 2,
@@ -773,16 +1123,22 @@ impl CPU {
     fn debug_instruction(
         &self,
         bus: &MemoryBus,
-        writer: &mut dyn std::io::Write,
+        writer: &mut dyn LogWrite,
         decoded: &DecodedInstruction,
     ) {
-        // C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+        // C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
         // PC    < raw >   < assembly >                    < registers >             < timing >
         let prev_ppu_address = bus.ppu.last_read.get();
+        let prev_ppu_open_bus = bus.ppu.open_bus.get();
+        let prev_ppu_open_bus_refreshed = bus.ppu.open_bus_refreshed.get();
+        let prev_frame_irq = bus.apu.frame_irq.get();
+        let prev_accesses = bus.access_log.borrow().len();
+        // the console steps the PPU after the instruction, so this is where it was when the instruction started
+        let (scanline, dot) = bus.ppu.position();
 
         // alocate a string on the stack, because it's fixed size and we can keep track of the position information
         // as it grows. once complete, there's a single copy to the writer
-        use std::fmt::Write;
+        use core::fmt::Write;
         let mut str_buf = arrayvec::ArrayString::<120>::new();
 
         write!(str_buf, "{:04X}  ", self.pc).unwrap();
@@ -898,41 +1254,74 @@ impl CPU {
 
         write!(
             str_buf,
-            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
-            self.a, self.x, self.y, self.status, self.sp, self.cycles
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+            self.a, self.x, self.y, self.status, self.sp, scanline, dot, self.cycles
         )
         .unwrap();
 
-        writer.write(&str_buf.as_bytes()).unwrap();
+        write!(writer, "{}", str_buf).unwrap();
 
-        // restore the PPU last read address
+        // restore the PPU last read address and data bus, and the frame IRQ acknowledged by $4015
         bus.ppu.last_read.set(prev_ppu_address);
+        bus.ppu.open_bus.set(prev_ppu_open_bus);
+        bus.ppu.open_bus_refreshed.set(prev_ppu_open_bus_refreshed);
+        bus.access_log.borrow_mut().truncate(prev_accesses);
+        bus.apu.frame_irq.set(prev_frame_irq);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
     use crate::cartridge;
     use crate::console::Console;
     use crate::ines;
+    use crate::instructions::{Opcode, EXTENDED_OPCODES};
 
-    use crate::bus::MemoryBus;
-    use crate::cpu::CPU;
+    use super::{make_address, page_wrap_next, stack_address, zp_wrap};
 
     #[test]
-    fn test_debug_log() {
-        let mut log_file = std::fs::File::create("tests/nestest.log").unwrap();
-        let mut rom_file = std::fs::File::open("tests/nestest.nes").unwrap();
-        let (c, m) = ines::load(&mut rom_file).expect("failed to load cartridge");
-
-        let mut console = Console::new(cartridge::new(c, m).unwrap());
-        console.cpu.pc = 0xc000;
-
-        // match offset for nestest.nes
-        cpu.cycles = 7;
+    fn test_page_wrapping() {
+        assert_eq!(make_address(0x34, 0x12), 0x1234);
+        assert_eq!(zp_wrap(0x00ff + 0x01), 0x0000);
+        assert_eq!(zp_wrap(0x00ff + 0xff), 0x00fe);
+        assert_eq!(page_wrap_next(0x02ff), 0x0200);
+        assert_eq!(page_wrap_next(0x00ff), 0x0000);
+        assert_eq!(stack_address(0x00), 0x0100);
+        assert_eq!(stack_address(0xff), 0x01ff);
+    }
 
-        for _ in 0..8991 {
-            cpu.step(&mut bus, Some(&mut log_file));
-        }
+    #[test]
+    fn test_dispatch_all_opcodes() {
+        // unofficial opcodes that aren't emulated yet
+        const UNIMPLEMENTED: [Opcode; 10] = [
+            Opcode::AHX,
+            Opcode::ALR,
+            Opcode::ANC,
+            Opcode::ARR,
+            Opcode::AXS,
+            Opcode::LAS,
+            Opcode::SHX,
+            Opcode::SHY,
+            Opcode::TAS,
+            Opcode::XAA,
+        ];
+
+        let failed: Vec<u8> = (0..=255u8)
+            .filter(|opcode| !UNIMPLEMENTED.contains(&EXTENDED_OPCODES[*opcode as usize].opcode))
+            .filter(|opcode| {
+                let mut prg = [0u8; 0x4000];
+                prg[0] = *opcode;
+                prg[0x3ffc..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+                let image = ines::ines_image(&[1, 1, 0], &prg);
+
+                let (c, m) = ines::load_bytes(&image).unwrap();
+                let mut console = Console::new(cartridge::new(c, m).unwrap());
+                catch_unwind(AssertUnwindSafe(|| console.step_instruction())).is_err()
+            })
+            .collect();
+
+        assert!(failed.is_empty(), "opcodes panicked: {:02x?}", failed);
     }
 }
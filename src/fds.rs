@@ -0,0 +1,271 @@
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cartridge::{ChrBank, Mapper, MirroringMode, CHR};
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
+
+// https://www.nesdev.org/wiki/FDS_file_format
+const MAGIC: [u8; 4] = *b"FDS\x1a";
+const HEADER_SIZE: usize = 16;
+pub const SIDE_SIZE: usize = 65500;
+
+// https://www.nesdev.org/wiki/FDS_disk_format
+// every side starts with the disk info block: block code 1, then the verification string
+const DISK_INFO_BLOCK: u8 = 0x01;
+const VERIFICATION: &[u8; 14] = b"*NINTENDO-HVC*";
+
+// the RAM adaptor has no iNES number of its own, mapper 20 is reserved for it
+const FDS_MAPPER_NUMBER: u8 = 20;
+
+pub type BiosRom = [u8; 0x2000];
+
+pub struct DiskImage {
+    sides: Vec<Vec<u8>>,
+}
+
+impl DiskImage {
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    pub fn side(&self, side: usize) -> Option<&[u8]> {
+        self.sides.get(side).map(Vec::as_slice)
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn load<R: std::io::Read>(reader: &mut R) -> Option<DiskImage> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).ok()?;
+
+    load_bytes(&buffer)
+}
+
+/// Load a `.fds` image, with or without the 16 byte fwNES header
+pub fn load_bytes(buffer: &[u8]) -> Option<DiskImage> {
+    let (side_count, data) = if buffer.starts_with(&MAGIC) {
+        let side_count = *buffer.get(4)? as usize;
+        (side_count, buffer.get(HEADER_SIZE..)?)
+    } else {
+        (buffer.len() / SIDE_SIZE, buffer)
+    };
+
+    if side_count == 0 || data.len() < side_count * SIDE_SIZE {
+        return None;
+    }
+
+    let sides: Vec<Vec<u8>> = data
+        .chunks_exact(SIDE_SIZE)
+        .take(side_count)
+        .map(<[u8]>::to_vec)
+        .collect();
+
+    if !sides
+        .iter()
+        .all(|side| side[0] == DISK_INFO_BLOCK && &side[1..15] == VERIFICATION)
+    {
+        return None;
+    }
+
+    Some(DiskImage { sides })
+}
+
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System
+// Only the memory map of the RAM adaptor is modeled so far: the disk drive, timer IRQ
+// and expansion audio are still missing.
+#[derive(Clone)]
+pub struct FdsAdapter {
+    bios: Rc<BiosRom>,
+    disk: Rc<DiskImage>,
+    side: usize,
+    prg_ram: Rc<[u8; 0x8000]>, // copy-on-write, like cartridge SRAM
+    chr: CHR,
+    mirror: MirroringMode,
+}
+
+impl FdsAdapter {
+    pub fn new(bios: BiosRom, disk: DiskImage) -> Self {
+        FdsAdapter {
+            bios: Rc::new(bios),
+            disk: Rc::new(disk),
+            side: 0,
+            prg_ram: Rc::new([0; 0x8000]),
+            chr: CHR::RAM(Rc::new(vec![[0u8; 0x2000]])),
+            mirror: MirroringMode::Horizontal,
+        }
+    }
+
+    /// Flip or change the disk to `side`, counting from 0 for side A of the first disk.
+    /// Returns false if the image doesn't have that side.
+    pub fn swap_disk_side(&mut self, side: usize) -> bool {
+        if side >= self.disk.side_count() {
+            return false;
+        }
+
+        self.side = side;
+        true
+    }
+
+    pub fn disk_side(&self) -> usize {
+        self.side
+    }
+}
+
+impl Mapper for FdsAdapter {
+    fn number(&self) -> u8 {
+        FDS_MAPPER_NUMBER
+    }
+
+    fn mirror(&self) -> MirroringMode {
+        self.mirror
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1fff => self.chr.get_banks()[0][address as usize],
+            // $4030-$4033: disk status, drive and expansion port registers aren't modeled yet
+            0x2000..=0x5fff => 0,
+            // CPU $6000-$DFFF: 32 KB PRG RAM
+            0x6000..=0xdfff => self.prg_ram[address as usize - 0x6000],
+            // CPU $E000-$FFFF: 8 KB BIOS ROM
+            0xe000.. => self.bios[address as usize - 0xe000],
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x0000..=0x1fff => {
+                if let Some(banks) = self.chr.get_banks_mut() {
+                    banks[0][address as usize] = data;
+                }
+            }
+            0x4025 => {
+                // FDS control, bit 3: 0 = vertical, 1 = horizontal mirroring
+                self.mirror = if data & 0b1000 == 0 {
+                    MirroringMode::Vertical
+                } else {
+                    MirroringMode::Horizontal
+                };
+            }
+            0x2000..=0x5fff => {}
+            0x6000..=0xdfff => Rc::make_mut(&mut self.prg_ram)[address as usize - 0x6000] = data,
+            0xe000.. => {}
+        }
+    }
+
+    fn is_register_write(&self, address: u16) -> bool {
+        // PRG RAM, up to the BIOS ROM at $E000
+        address < 0xe000
+    }
+
+    fn read_page(&self, page: u8) -> Option<&[u8; 256]> {
+        let offset = (page as usize) << 8;
+
+        match page {
+            0x00..=0x7f => None,
+            0x80..=0xdf => self.prg_ram[offset - 0x6000..][..256].try_into().ok(),
+            0xe0.. => self.bios[offset - 0xe000..][..256].try_into().ok(),
+        }
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        self.prg_ram.len()
+    }
+
+    // the BIOS is the only ROM, the game is loaded from disk into PRG RAM
+    fn prg_rom_size(&self) -> usize {
+        self.bios.len()
+    }
+
+    fn has_chr_ram(&self) -> bool {
+        true
+    }
+
+    fn chr_banks(&self) -> &[ChrBank] {
+        self.chr.get_banks()
+    }
+
+    fn chr_banks_mut(&mut self) -> Option<&mut [ChrBank]> {
+        self.chr.get_banks_mut().map(Vec::as_mut_slice)
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.side as u8);
+        state.write_bool(self.mirror == MirroringMode::Horizontal);
+        state.write_bytes(self.prg_ram.as_slice());
+        state.write_bytes(&self.chr.get_banks()[0]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        if !self.swap_disk_side(state.read_u8()? as usize) {
+            return Err(LoadStateError::Corrupt);
+        }
+
+        self.mirror = if state.read_bool()? {
+            MirroringMode::Horizontal
+        } else {
+            MirroringMode::Vertical
+        };
+        state.read_bytes(Rc::make_mut(&mut self.prg_ram).as_mut_slice())?;
+        if let Some(banks) = self.chr.get_banks_mut() {
+            state.read_bytes(&mut banks[0])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FdsAdapter, MAGIC, SIDE_SIZE, VERIFICATION};
+    use crate::cartridge::Mapper;
+
+    fn disk_side() -> Vec<u8> {
+        let mut side = vec![0u8; SIDE_SIZE];
+        side[0] = 0x01;
+        side[1..15].copy_from_slice(VERIFICATION);
+        side
+    }
+
+    #[test]
+    fn test_load_header() {
+        let mut image = Vec::new();
+        image.extend_from_slice(&MAGIC);
+        image.push(2);
+        image.resize(16, 0);
+        image.extend(disk_side());
+        image.extend(disk_side());
+
+        let disk = super::load(&mut image.as_slice()).unwrap();
+        assert_eq!(disk.side_count(), 2);
+
+        // headerless images infer the side count from the size
+        let disk = super::load(&mut &image[16..]).unwrap();
+        assert_eq!(disk.side_count(), 2);
+
+        // missing the second side
+        assert!(super::load(&mut &image[..16 + SIDE_SIZE]).is_none());
+
+        // bad verification string
+        image[16 + 1] = b'_';
+        assert!(super::load(&mut image.as_slice()).is_none());
+    }
+
+    #[test]
+    fn test_swap_disk_side() {
+        let disk = super::load(&mut disk_side().as_slice()).unwrap();
+        let mut bios = [0u8; 0x2000];
+        bios[0x1ffc] = 0x24;
+        let mut adapter = FdsAdapter::new(bios, disk);
+
+        assert_eq!(adapter.read(0xfffc), 0x24);
+        adapter.write(0x6000, 0x12);
+        adapter.write(0xdfff, 0x34);
+        assert_eq!((adapter.read(0x6000), adapter.read(0xdfff)), (0x12, 0x34));
+
+        assert!(adapter.swap_disk_side(0));
+        assert!(!adapter.swap_disk_side(1));
+        assert_eq!(adapter.disk_side(), 0);
+    }
+}
@@ -1,4 +1,6 @@
-use std::rc::Rc;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
 
 use crate::cartridge::{ChrBank, ProgBank, CHR, PRG};
 
@@ -7,6 +9,9 @@ use super::cartridge::Cartridge;
 
 const MAGIC: [u8; 4] = ['N' as u8, 'E' as u8, 'S' as u8, 0x1a];
 
+// PRG RAM banks mapped at $6000
+const MAPPED_PRG_RAM_BANKS: u8 = 1;
+
 // https://www.nesdev.org/wiki/INES
 #[derive(Default)]
 struct INESHeader {
@@ -22,21 +27,22 @@ struct INESHeader {
     playchoice10: bool, // 7 1
     nes2: bool,         // 7 2..3
     // mapper_hi // 7 4..6
-    ram_size: u8, // 8
-    pal: bool,    // 9 1
+    ram_size: u8,  // 8 (iNES)
+    submapper: u8, // 8 4..7 (NES 2.0)
+    pal: bool,     // 9 1
     // ignored   // 9 2.. 7
     tv_system_prg_ram_presence: u8, // 10
-    // ignored // 11-15
-    mapper: u8, // mapper_hi << 4 | mapper_lo
+    // ignored // 11-14
+    expansion_device: u8, // 15 0..5 (NES 2.0)
+    mapper: u8,           // mapper_hi << 4 | mapper_lo
 }
 
 impl INESHeader {
-    fn parse<R: std::io::Read>(reader: &mut R) -> Option<INESHeader> {
-        let mut buffer: [u8; 16] = [0; 16];
+    fn parse(reader: &mut &[u8]) -> Option<INESHeader> {
         let mut ines_header = INESHeader::default();
 
-        // read exactly 10 bytes
-        reader.read_exact(buffer.as_mut_slice()).ok()?;
+        // read exactly 16 bytes
+        let mut buffer: [u8; 16] = take(reader, 16)?.try_into().ok()?;
 
         if &buffer[..4] != &MAGIC[..] {
             return None;
@@ -47,22 +53,39 @@ impl INESHeader {
         ines_header.chr_banks = buffer[5];
         ines_header.mirror = (buffer[6] & 0b0001) != 0;
         ines_header.has_battery = (buffer[6] & 0b0010) != 0;
-        ines_header.has_battery = (buffer[6] & 0b0100) != 0;
         ines_header.has_trainer = (buffer[6] & 0b0100) != 0;
         ines_header.four_screen_mirror = (buffer[6] & 0b1000) != 0;
-        ines_header.mapper = buffer[6] >> 4;
-        ines_header.vs_unisystem = buffer[7] & 0b0001 != 0;
-        ines_header.playchoice10 = buffer[7] & 0b0010 != 0;
-        ines_header.nes2 = buffer[7] & 0b1100 == 0b1000;
-        ines_header.mapper = buffer[7] >> 4;
-        ines_header.ram_size = buffer[8];
+
+        // old rippers wrote signatures like "DiskDude!" over bytes 7-15, which turns
+        // them into garbage. They can only be trusted for NES 2.0 headers, or when
+        // the unused bytes 12-15 are zero, and are read as zero otherwise.
+        // https://www.nesdev.org/wiki/INES#Variant_comparison
+        let nes2 = buffer[7] & 0b1100 == 0b1000;
+        if !nes2 && (buffer[7] & 0b1100 != 0 || buffer[12..16] != [0; 4]) {
+            buffer[7..].fill(0);
+        }
+        let flags7 = buffer[7];
+
+        ines_header.vs_unisystem = flags7 & 0b0001 != 0;
+        ines_header.playchoice10 = flags7 & 0b0010 != 0;
+        ines_header.nes2 = nes2;
+        ines_header.mapper = flags7 & 0xf0 | buffer[6] >> 4;
+        // NES 2.0 moved the PRG RAM size out of byte 8, which now holds the submapper
+        // that tells revisions of a board apart
+        // https://www.nesdev.org/wiki/NES_2.0#Submapper_number
+        if nes2 {
+            ines_header.submapper = buffer[8] >> 4;
+            ines_header.expansion_device = buffer[15] & 0x3f;
+        } else {
+            ines_header.ram_size = buffer[8];
+        }
         ines_header.pal = buffer[9] & 0b1 != 0;
         ines_header.tv_system_prg_ram_presence = buffer[10];
 
         Some(ines_header)
     }
 
-    fn read<R: std::io::Read>(&self, reader: &mut R) -> Option<cartridge::Cartridge> {
+    fn read(&self, reader: &mut &[u8]) -> Option<cartridge::Cartridge> {
         // https://www.nesdev.org/wiki/INES
         // 1. Header (16 bytes)
         // 2. Trainer, if present (0 or 512 bytes)
@@ -82,12 +105,12 @@ impl INESHeader {
         }
 
         for bank in &mut prg_banks {
-            reader.read_exact(bank.as_mut_slice()).ok()?;
+            bank.copy_from_slice(take(reader, size_of::<ProgBank>())?);
         }
 
         // load CHR ROM / CHR RAM
         let chr = if self.chr_banks == 0 {
-            CHR::RAM(vec![[0u8; 8192]])
+            CHR::RAM(Rc::new(vec![[0u8; 8192]]))
         } else {
             let mut chr_banks: Vec<ChrBank> = Vec::with_capacity(self.chr_banks as usize);
             unsafe {
@@ -96,19 +119,24 @@ impl INESHeader {
             }
 
             for bank in &mut chr_banks {
-                reader.read_exact(bank.as_mut_slice()).ok()?;
+                bank.copy_from_slice(take(reader, size_of::<ChrBank>())?);
             }
 
             CHR::ROM(Rc::new(chr_banks))
         };
 
-        // PRG RAM??
-        ();
+        // PRG RAM: a size of 0 infers 8 KB for compatibility, and the boards only map
+        // 8 KB at $6000, so there's no use for more
+        // https://www.nesdev.org/wiki/INES#Flags_8
+        let sram = vec![[0u8; 0x2000]; self.ram_size.clamp(1, MAPPED_PRG_RAM_BANKS) as usize];
 
         Some(Cartridge {
-            prg: Rc::new(PRG { banks: prg_banks }),
+            prg: Rc::new(PRG::new(prg_banks)),
             chr,
-            sram: Vec::with_capacity(self.ram_size as usize),
+            sram: Rc::new(sram),
+            has_battery: self.has_battery,
+            vs_system: self.vs_unisystem,
+            submapper: self.submapper,
             mirror: match (self.four_screen_mirror, self.mirror) {
                 (true, _) => cartridge::MirroringMode::FourScreen,
                 (false, false) => cartridge::MirroringMode::Horizontal,
@@ -118,9 +146,90 @@ impl INESHeader {
     }
 }
 
-pub fn load<R: std::io::Read>(reader: &mut R) -> Option<(cartridge::Cartridge, u8)> {
-    let header = INESHeader::parse(reader)?;
-    let cartridge = header.read(reader)?;
+// consume the next `len` bytes of the image
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if reader.len() < len {
+        return None;
+    }
+
+    let (head, tail) = reader.split_at(len);
+    *reader = tail;
+    Some(head)
+}
+
+/// Load a cartridge from an iNES image in memory, along with its mapper number
+pub fn load_bytes(mut data: &[u8]) -> Option<(cartridge::Cartridge, u8)> {
+    let header = INESHeader::parse(&mut data)?;
+    let cartridge = header.read(&mut data)?;
 
     Some((cartridge, header.mapper))
 }
+
+/// The NES 2.0 default expansion device of an image, the input devices the game expects,
+/// or 0 when unspecified
+/// https://www.nesdev.org/wiki/NES_2.0#Default_Expansion_Device
+pub(crate) fn default_expansion_device(mut data: &[u8]) -> u8 {
+    INESHeader::parse(&mut data).map_or(0, |header| header.expansion_device)
+}
+
+#[cfg(feature = "std")]
+pub fn load<R: std::io::Read>(reader: &mut R) -> Option<(cartridge::Cartridge, u8)> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).ok()?;
+
+    load_bytes(&data)
+}
+
+// An iNES image for tests. `header` is the header after the magic number, padded with zeros,
+// and `prg` is the start of the PRG ROM banks it asks for. The rest, and the CHR ROM, are zeros
+#[cfg(test)]
+pub(crate) fn ines_image(header: &[u8], prg: &[u8]) -> Vec<u8> {
+    let mut image = MAGIC.to_vec();
+    image.extend_from_slice(header);
+    image.resize(16, 0);
+    image.extend_from_slice(prg);
+    image.resize(
+        16 + header[0] as usize * 0x4000 + header[1] as usize * 0x2000,
+        0,
+    );
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ines_image;
+
+    #[test]
+    fn test_mapper_number() {
+        let (_, mapper) = super::load_bytes(&ines_image(&[1, 1, 0x41, 0x10], &[])).unwrap();
+        assert_eq!(mapper, 0x14);
+    }
+
+    #[test]
+    fn test_disk_dude() {
+        let mut header = [0u8; 12];
+        header[..3].copy_from_slice(&[1, 1, 0x31]);
+        header[3..].copy_from_slice(b"DiskDude!");
+        let image = ines_image(&header, &[]);
+
+        // 'D' would otherwise make the high nibble 4
+        let (cartridge, mapper) = super::load_bytes(&image).unwrap();
+        assert_eq!(mapper, 3);
+
+        // and 'i' 105 banks of PRG RAM
+        let mapper = crate::cartridge::new(cartridge, mapper).unwrap();
+        assert_eq!(mapper.prg_ram_size(), 0x2000);
+    }
+
+    #[test]
+    fn test_nes2_submapper() {
+        let image = ines_image(&[1, 1, 0x10, 0x08, 0x50], &[]);
+        let mut data = &image[..];
+        let ines_header = super::INESHeader::parse(&mut data).unwrap();
+        assert_eq!(ines_header.submapper, 5);
+
+        // the submapper isn't mistaken for 5 * 16 PRG RAM banks
+        let cartridge = ines_header.read(&mut data).unwrap();
+        assert_eq!(cartridge.sram.len(), 1);
+    }
+}
@@ -1,5 +1,5 @@
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub(crate) enum Opcode {
+pub enum Opcode {
     ADC,
     AHX,
     ALR,
@@ -78,7 +78,7 @@ pub(crate) enum Opcode {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub(crate) enum AddressingMode {
+pub enum AddressingMode {
     Implied,
     Accumulator,      // A
     Absolute,         // $xxxx       a
@@ -149,6 +149,8 @@ pub(crate) struct ExtendedOpcode {
     pub(crate) addressing_mode: AddressingMode,
     pub(crate) min_cycles: u8,
     pub(crate) page_boundary_penalty: bool,
+    // documented by MOS, rather than a side effect of the decoder
+    pub(crate) official: bool,
 }
 
 pub(crate) const EXTENDED_OPCODES: [ExtendedOpcode; 256] = [
@@ -157,1541 +159,1798 @@ pub(crate) const EXTENDED_OPCODES: [ExtendedOpcode; 256] = [
         addressing_mode: AddressingMode::Implied,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ASL,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::PHP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ASL,
         addressing_mode: AddressingMode::Accumulator,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ANC,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ASL,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BPL,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ASL,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CLC,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ORA,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ASL,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SLO,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::JSR,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BIT,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROL,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::PLP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROL,
         addressing_mode: AddressingMode::Accumulator,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ANC,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BIT,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROL,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BMI,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROL,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SEC,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::AND,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROL,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RLA,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RTI,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LSR,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::PHA,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LSR,
         addressing_mode: AddressingMode::Accumulator,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ALR,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::JMP,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LSR,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BVC,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LSR,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CLI,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::EOR,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LSR,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SRE,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RTS,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROR,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::PLA,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROR,
         addressing_mode: AddressingMode::Accumulator,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ARR,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::JMP,
         addressing_mode: AddressingMode::Indirect,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROR,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BVS,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROR,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SEI,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ADC,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ROR,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::RRA,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SAX,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::STY,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STX,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SAX,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::DEY,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::TXA,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::XAA,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::STY,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STX,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SAX,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BCC,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::AHX,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::STY,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STX,
         addressing_mode: AddressingMode::ZeroPageIndexedY,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SAX,
         addressing_mode: AddressingMode::ZeroPageIndexedY,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::TYA,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::TXS,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::TAS,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SHY,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::STA,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SHX,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::AHX,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LDY,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDX,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LDY,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDX,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::TAY,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::TAX,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LDY,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDX,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BCS,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LDY,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDX,
         addressing_mode: AddressingMode::ZeroPageIndexedY,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::ZeroPageIndexedY,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CLV,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::TSX,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAS,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::LDY,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDA,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LDX,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::LAX,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPY,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPY,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DEC,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::INY,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DEX,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::AXS,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPY,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DEC,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BNE,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DEC,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CLD,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CMP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DEC,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::DCP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPX,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::IndexedIndirect,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPX,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 3,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::INC,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::ZeroPage,
         min_cycles: 5,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::INX,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::Immediate,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::CPX,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::INC,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::Absolute,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::BEQ,
         addressing_mode: AddressingMode::Relative,
         min_cycles: 2,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 5,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::STP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::IndirectIndexed,
         min_cycles: 8,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 4,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::INC,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::ZeroPageIndexedX,
         min_cycles: 6,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SED,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::Implied,
         min_cycles: 2,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::AbsoluteIndexedY,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::NOP,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: false,
     },
     ExtendedOpcode {
         opcode: Opcode::SBC,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 4,
         page_boundary_penalty: true,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::INC,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: true,
     },
     ExtendedOpcode {
         opcode: Opcode::ISB,
         addressing_mode: AddressingMode::AbsoluteIndexedX,
         min_cycles: 7,
         page_boundary_penalty: false,
+        official: false,
     },
 ];
 
 #[derive(Clone, Debug)]
 pub(crate) struct DecodedInstruction {
+    pub(crate) opcode_byte: u8,
     pub(crate) extended_opcode: &'static ExtendedOpcode,
     pub(crate) address_info: AddressInfo,
     pub(crate) final_address: Option<u16>,
@@ -1,10 +1,17 @@
-pub(crate) mod apu;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod apu;
 pub(crate) mod bus;
 pub mod cartridge;
 pub mod console;
 pub mod controller;
 pub mod cpu;
+pub mod fds;
 pub mod ines;
-mod instructions;
-pub(crate) mod ppu;
+pub mod instructions;
+pub mod ppu;
 pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod wav;
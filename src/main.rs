@@ -2,7 +2,8 @@ use clap::builder::Str;
 use clap::Parser;
 use image::{write_buffer_with_format, GrayImage, ImageBuffer, Luma};
 use nes::controller::ButtonState;
-use nes::{cartridge, console::Console, controller::Button};
+use nes::ppu::{decode_tile_row, SCREEN_HEIGHT, SCREEN_WIDTH};
+use nes::{console::Console, controller::Button, wav::WavWriter};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::{Color, PixelFormatEnum};
@@ -13,17 +14,6 @@ use std::collections::VecDeque;
 use std::process::exit;
 use std::time::Duration;
 
-const PALETTE_RGB: [u32; 64] = [
-    0x666666, 0x002A88, 0x1412A7, 0x3B00A4, 0x5C007E, 0x6E0040, 0x6C0600, 0x561D00, 0x333500,
-    0x0B4800, 0x005200, 0x004F08, 0x00404D, 0x000000, 0x000000, 0x000000, 0xADADAD, 0x155FD9,
-    0x4240FF, 0x7527FE, 0xA01ACC, 0xB71E7B, 0xB53120, 0x994E00, 0x6B6D00, 0x388700, 0x0C9300,
-    0x008F32, 0x007C8D, 0x000000, 0x000000, 0x000000, 0xFFFEFF, 0x64B0FF, 0x9290FF, 0xC676FF,
-    0xF36AFF, 0xFE6ECC, 0xFE8170, 0xEA9E22, 0xBCBE00, 0x88D800, 0x5CE430, 0x45E082, 0x48CDDE,
-    0x4F4F4F, 0x000000, 0x000000, 0xFFFEFF, 0xC0DFFF, 0xD3D2FF, 0xE8C8FF, 0xFBC2FF, 0xFEC4EA,
-    0xFECCC5, 0xF7D8A5, 0xE4E594, 0xCFEF96, 0xBDF4AB, 0xB3F3CC, 0xB5EBF2, 0xB8B8B8, 0x000000,
-    0x000000,
-];
-
 fn get_button(keycode: Keycode) -> Option<Button> {
     match keycode {
         Keycode::W => Some(Button::Up),
@@ -62,14 +52,9 @@ fn save_png(rom_path: &str, bmp_path: &str) {
             let top_y = 1 + 9 * (tile_no / tiles_x);
 
             for tile_y in 0..8usize {
-                let lo_y = tile[tile_y];
-                let hi_y = tile[tile_y + 8];
-
-                for tile_x in 0..8usize {
-                    let lo_px = (lo_y >> (7 - tile_x)) & 0b1;
-                    let hi_px = (hi_y >> (7 - tile_x)) & 0b1;
-                    let px = hi_px << 1 | lo_px;
+                let row = decode_tile_row(tile[tile_y], tile[tile_y + 8]);
 
+                for (tile_x, px) in row.into_iter().enumerate() {
                     img.put_pixel(
                         (left_x + tile_x) as u32,
                         (top_y + tile_y) as u32,
@@ -91,17 +76,31 @@ fn save_png(rom_path: &str, bmp_path: &str) {
     .expect("failed to save image")
 }
 
-fn play_rom(rom_path: &str, cpu_ignore_rewind: Vec<u16>, ppu_ignore_rewind: Vec<u16>) {
+fn play_rom(
+    rom_path: &str,
+    cpu_ignore_rewind: Vec<u16>,
+    ppu_ignore_rewind: Vec<u16>,
+    record_audio: Option<String>,
+) {
     const SCALING: u32 = 2;
-    const WIDTH: u32 = 256;
-    const HEIGHT: u32 = 240;
+    const SAMPLE_RATE: u32 = 44_100;
+    const WIDTH: u32 = SCREEN_WIDTH as u32;
+    const HEIGHT: u32 = SCREEN_HEIGHT as u32;
     let frame_duration = Duration::from_secs(1) / 60;
 
     let mut rom_file = std::fs::File::open(rom_path).unwrap();
 
-    let (c, m) = nes::ines::load(&mut rom_file).expect("failed to load cartridge");
-    let mapper = cartridge::new(c, m).unwrap();
-    let mut console = Console::new(mapper);
+    let mut console = Console::load_rom(&mut rom_file).expect("failed to load cartridge");
+    console
+        .set_rewind_preserved_addresses(cpu_ignore_rewind, ppu_ignore_rewind)
+        .expect("invalid address to preserve on rewind");
+    console.set_audio_sample_rate(SAMPLE_RATE);
+
+    let mut recording = record_audio.map(|path| {
+        let wav_file = std::fs::File::create(path).expect("failed to create audio recording");
+        WavWriter::new(std::io::BufWriter::new(wav_file), SAMPLE_RATE)
+            .expect("failed to write audio recording")
+    });
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -126,10 +125,10 @@ fn play_rom(rom_path: &str, cpu_ignore_rewind: Vec<u16>, ppu_ignore_rewind: Vec<
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, WIDTH * SCALING, HEIGHT * SCALING)
+        .create_texture_streaming(PixelFormatEnum::RGB24, WIDTH, HEIGHT)
         .unwrap();
 
-    let mut raw_texture = [0 as u8; (WIDTH * HEIGHT * SCALING * SCALING * 3) as usize];
+    let mut raw_texture = [0 as u8; (WIDTH * HEIGHT * 3) as usize];
 
     let mut rewind = false;
     let mut button_state = ButtonState::default();
@@ -146,6 +145,13 @@ fn play_rom(rom_path: &str, cpu_ignore_rewind: Vec<u16>, ppu_ignore_rewind: Vec<
                 } => {
                     break 'run_loop;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    repeat: false,
+                    ..
+                } => {
+                    console.reset();
+                }
                 Event::KeyDown {
                     keycode: Some(k), ..
                 } => {
@@ -181,38 +187,52 @@ fn play_rom(rom_path: &str, cpu_ignore_rewind: Vec<u16>, ppu_ignore_rewind: Vec<
 
         let screen = console.next_screen();
 
-        for (y, row) in screen.pixels.iter().enumerate() {
-            for (x, palette_color) in row.iter().enumerate() {
-                // decode the palette
-                let [_, r, g, b] = PALETTE_RGB[*palette_color as usize].to_be_bytes();
-
-                for y_off in 0..SCALING {
-                    let row_start =
-                        (y * SCALING as usize + y_off as usize) * (WIDTH * SCALING) as usize;
-                    for x_off in 0..SCALING {
-                        let column_offset = x * SCALING as usize + x_off as usize;
-                        let px_offset = (row_start + column_offset) * 3;
-
-                        raw_texture[px_offset] = r;
-                        raw_texture[px_offset + 1] = g;
-                        raw_texture[px_offset + 2] = b;
-                    }
-                }
-            }
-        }
+        screen
+            .write_rgb(&mut raw_texture)
+            .expect("texture doesn't match the screen");
 
         texture
-            .update(None, &raw_texture, (SCALING * WIDTH * 3) as usize)
+            .update(None, &raw_texture, (WIDTH * 3) as usize)
             .unwrap();
         canvas.copy(&texture, None, None).unwrap();
         canvas.present();
 
+        if let Some(recording) = &mut recording {
+            recording
+                .write_samples(&console.drain_audio())
+                .expect("failed to write audio recording");
+        }
+
         // sleep for 1/60th of a second
         let elapsed = pre_draw.elapsed();
         if elapsed < frame_duration {
             std::thread::sleep(frame_duration - elapsed);
         }
     }
+
+    if let Some(recording) = recording {
+        recording
+            .finish()
+            .expect("failed to finish audio recording");
+    }
+}
+
+fn bench(rom_path: &str, frames: usize) {
+    let mut rom_file = std::fs::File::open(rom_path).unwrap();
+    let mut console = Console::load_rom(&mut rom_file).expect("failed to load cartridge");
+    console.set_rewind_enabled(false);
+
+    let start = std::time::Instant::now();
+    console.run_frames(frames).unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "{} frames in {:.3}s: {:.1} fps, {:.0} cycles/s",
+        frames,
+        elapsed,
+        frames as f64 / elapsed,
+        console.cpu_cycles() as f64 / elapsed
+    );
 }
 
 #[derive(clap::Parser)]
@@ -224,6 +244,9 @@ enum CLI {
         cpu_ignore_rewind: Vec<u16>,
         #[arg(short, long)]
         ppu_ignore_rewind: Vec<u16>,
+        /// Record the audio to a .wav file
+        #[arg(long)]
+        record_audio: Option<String>,
     },
     CHRDump {
         #[arg(long)]
@@ -231,6 +254,13 @@ enum CLI {
         #[arg(long)]
         out: String,
     },
+    /// Run a ROM headless as fast as possible, and report the speed
+    Bench {
+        #[arg(long)]
+        rom: String,
+        #[arg(long, default_value_t = 10000)]
+        frames: usize,
+    },
 }
 
 fn main() {
@@ -238,10 +268,12 @@ fn main() {
 
     match args {
         CLI::CHRDump { rom, out } => save_png(&rom, &out),
+        CLI::Bench { rom, frames } => bench(&rom, frames),
         CLI::Play {
             rom,
             cpu_ignore_rewind,
             ppu_ignore_rewind,
-        } => play_rom(&rom, cpu_ignore_rewind, ppu_ignore_rewind),
+            record_audio,
+        } => play_rom(&rom, cpu_ignore_rewind, ppu_ignore_rewind, record_audio),
     };
 }
@@ -1,6 +1,7 @@
-use std::cell::Cell;
+use core::cell::Cell;
 
 use crate::cartridge::{Mapper, MirroringMode};
+use crate::snapshot::{LoadStateError, StateReader, StateWriter};
 
 struct PPUControl {
     base_nametable: u8, // two bits
@@ -59,9 +60,20 @@ struct PPUStatus {
     nmi_occurred: bool,
 }
 
+impl From<u8> for PPUStatus {
+    fn from(raw: u8) -> Self {
+        PPUStatus {
+            open_bus: raw & 0b1_1111,
+            sprite_overflow: (raw & 0b0010_0000) != 0,
+            sprite_zero_hit: (raw & 0b0100_0000) != 0,
+            nmi_occurred: (raw & 0b1000_0000) != 0,
+        }
+    }
+}
+
 impl From<PPUStatus> for u8 {
     fn from(status: PPUStatus) -> u8 {
-        (status.open_bus & 0b1_0000)
+        (status.open_bus & 0b1_1111)
             | (status.sprite_overflow as u8) << 5
             | (status.sprite_zero_hit as u8) << 6
             | (status.nmi_occurred as u8) << 7
@@ -130,6 +142,30 @@ impl From<u16> for VRAMAddress {
     }
 }
 
+/// The scroll position held in the current VRAM address, decoded for debugging.
+/// Coarse values count 8 pixel tiles, fine values pixels within a tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollInfo {
+    pub coarse_x: u8,
+    pub coarse_y: u8,
+    pub fine_x: u8,
+    pub fine_y: u8,
+    /// Nametable 0-3, in the order $2000, $2400, $2800, $2C00
+    pub nametable: u8,
+}
+
+/// Combine a row of a tile's low and high bit planes into the 2-bit color indices
+/// of its 8 pixels, leftmost first
+pub fn decode_tile_row(lo: u8, hi: u8) -> [u8; 8] {
+    core::array::from_fn(|x| decode_tile_pixel(lo, hi, x as u8))
+}
+
+// the 2-bit color index of pixel `x` of a row, 0 being the leftmost
+fn decode_tile_pixel(lo: u8, hi: u8, x: u8) -> u8 {
+    let shift = 7 - x;
+    (((hi >> shift) & 0b1) << 1) | ((lo >> shift) & 0b1)
+}
+
 #[derive(Clone, Default, Debug)]
 struct TileData {
     nametable_index: u8,
@@ -139,11 +175,25 @@ struct TileData {
 }
 
 impl TileData {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[
+            self.nametable_index,
+            self.palette,
+            self.pattern_low,
+            self.pattern_high,
+        ]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.nametable_index = state.read_u8()?;
+        self.palette = state.read_u8()?;
+        self.pattern_low = state.read_u8()?;
+        self.pattern_high = state.read_u8()?;
+        Ok(())
+    }
+
     fn color(&self, x: u8) -> u8 {
-        let shift = 7 - x;
-        let lo = (self.pattern_low >> shift) & 0b1;
-        let hi = (self.pattern_high >> shift) & 0b1;
-        (hi << 1) | lo
+        decode_tile_pixel(self.pattern_low, self.pattern_high, x)
     }
 }
 
@@ -172,6 +222,20 @@ impl From<&[u8; 4]> for ParsedSprite {
     }
 }
 
+impl From<&ParsedSprite> for [u8; 4] {
+    fn from(sprite: &ParsedSprite) -> Self {
+        [
+            sprite.top_y,
+            sprite.tile_index,
+            sprite.palette
+                | (sprite.behind_background as u8) << 5
+                | (sprite.flip_horizontal as u8) << 6
+                | (sprite.flip_vertical as u8) << 7,
+            sprite.left_x,
+        ]
+    }
+}
+
 impl ParsedSprite {
     fn is_empty(&self) -> bool {
         self.top_y == 0xff && self.tile_index == 0xff && self.left_x == 0xff
@@ -194,18 +258,228 @@ impl ProcessedSprite {
     }
 }
 
+/// Visible pixels per scanline
+pub const SCREEN_WIDTH: usize = 256;
+/// Visible scanlines per frame
+pub const SCREEN_HEIGHT: usize = 240;
+/// Colors in the NES palette, which the pixels of a `Screen` index into
+pub const PALETTE_SIZE: usize = 64;
+/// PPU dots for every CPU cycle on NTSC
+pub const DOTS_PER_CPU_CYCLE: u64 = 3;
+/// Dots in a frame of 262 scanlines of 341 dots. Odd frames with rendering enabled are a dot short.
+pub const DOTS_PER_FRAME: u64 = 341 * 262;
+/// CPU cycles per frame with rendering enabled, averaged over the long and short frames
+pub const CPU_CYCLES_PER_FRAME: f64 = (DOTS_PER_FRAME as f64 - 0.5) / DOTS_PER_CPU_CYCLE as f64;
+
+// a BIT $2002 loop reads it every 7 CPU cycles, close to 4000 times over the visible scanlines.
+// a quarter of that tells a loop waiting on sprite zero apart from the odd read
+const SPRITE_ZERO_STALL_POLLS: u32 = 1000;
+
+// https://www.nesdev.org/wiki/PPU_palettes
+pub const PALETTE_RGB: [u32; PALETTE_SIZE] = [
+    0x666666, 0x002A88, 0x1412A7, 0x3B00A4, 0x5C007E, 0x6E0040, 0x6C0600, 0x561D00, 0x333500,
+    0x0B4800, 0x005200, 0x004F08, 0x00404D, 0x000000, 0x000000, 0x000000, 0xADADAD, 0x155FD9,
+    0x4240FF, 0x7527FE, 0xA01ACC, 0xB71E7B, 0xB53120, 0x994E00, 0x6B6D00, 0x388700, 0x0C9300,
+    0x008F32, 0x007C8D, 0x000000, 0x000000, 0x000000, 0xFFFEFF, 0x64B0FF, 0x9290FF, 0xC676FF,
+    0xF36AFF, 0xFE6ECC, 0xFE8170, 0xEA9E22, 0xBCBE00, 0x88D800, 0x5CE430, 0x45E082, 0x48CDDE,
+    0x4F4F4F, 0x000000, 0x000000, 0xFFFEFF, 0xC0DFFF, 0xD3D2FF, 0xE8C8FF, 0xFBC2FF, 0xFEC4EA,
+    0xFECCC5, 0xF7D8A5, 0xE4E594, 0xCFEF96, 0xBDF4AB, 0xB3F3CC, 0xB5EBF2, 0xB8B8B8, 0x000000,
+    0x000000,
+];
+
+// https://www.nesdev.org/wiki/PPU_palettes#2C04
+// the RP2C04-0001 used by Vs. System boards scrambles the palette, this maps each of its
+// colors to the 2C02 color closest to it
+const VS_PALETTE: [u8; PALETTE_SIZE] = [
+    0x35, 0x23, 0x16, 0x22, 0x1c, 0x09, 0x1d, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+    0x21, 0x3e, 0x1f, 0x29, 0x3c, 0x32, 0x36, 0x12, 0x3f, 0x2b, 0x2e, 0x1e, 0x3d, 0x2d, 0x24, 0x01,
+    0x0e, 0x31, 0x33, 0x2a, 0x2c, 0x0c, 0x1b, 0x14, 0x2e, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2e,
+    0x2e, 0x19, 0x10, 0x0a, 0x39, 0x03, 0x37, 0x17, 0x0f, 0x11, 0x0b, 0x0d, 0x38, 0x25, 0x18, 0x3a,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScreenError {
+    /// The output buffer doesn't hold exactly the bytes the (scaled) screen needs
+    DimensionMismatch { expected: usize, actual: usize },
+}
+
+impl core::fmt::Display for ScreenError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ScreenError::DimensionMismatch { expected, actual } => write!(
+                f,
+                "screen buffer is {} bytes, expected {} bytes",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ScreenError {}
+
+// https://www.nesdev.org/wiki/NTSC_video#Color_Tint_Bits
+// each emphasis bit darkens the other two color components
+const EMPHASIS_RED: u16 = 1 << 6;
+const EMPHASIS_GREEN: u16 = 1 << 7;
+const EMPHASIS_BLUE: u16 = 1 << 8;
+
+fn attenuate(component: u8) -> u8 {
+    // roughly 0.816
+    (component as u16 * 209 / 256) as u8
+}
+
+/// A frame drawn by the PPU. Each pixel holds its index into the palette in bits 0-5,
+/// and the PPUMASK emphasis bits for red, green and blue in bits 6-8.
 #[derive(Clone)]
 pub struct Screen {
-    // indexes into the palette
-    pub pixels: [[u8; 256]; 240],
+    pub(crate) pixels: [[u16; SCREEN_WIDTH]; SCREEN_HEIGHT],
 }
 
 impl Default for Screen {
     fn default() -> Self {
+        Screen::blank(0)
+    }
+}
+
+impl Screen {
+    /// A screen filled with a single pixel value
+    pub fn blank(color: u16) -> Self {
         Self {
-            pixels: [[0; 256]; 240],
+            pixels: [[color; SCREEN_WIDTH]; SCREEN_HEIGHT],
         }
     }
+
+    /// The scanlines from top to bottom, without copying
+    pub fn rows(&self) -> impl Iterator<Item = &[u16; SCREEN_WIDTH]> {
+        self.pixels.iter()
+    }
+
+    /// The palette index of the pixel at column `x` and row `y`, without emphasis
+    pub fn index_at(&self, x: usize, y: usize) -> u8 {
+        (self.pixels[y][x] & 0x3f) as u8
+    }
+
+    /// FNV-1a hash of the pixels, to cheaply tell whether two frames look the same
+    pub fn checksum(&self) -> u64 {
+        self.pixels
+            .iter()
+            .flatten()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, pixel| {
+                (hash ^ *pixel as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    /// Encode the screen as a PNG indexed by the NES palette, one palette index per pixel.
+    /// Emphasis isn't part of the NES palette, so it's left out.
+    #[cfg(feature = "png")]
+    pub fn write_indexed_png<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        let palette: Vec<u8> = PALETTE_RGB
+            .iter()
+            .flat_map(|rgb| {
+                let [_, r, g, b] = rgb.to_be_bytes();
+                [r, g, b]
+            })
+            .collect();
+        let indexes: Vec<u8> = self
+            .pixels
+            .iter()
+            .flatten()
+            .map(|pixel| (pixel & 0x3f) as u8)
+            .collect();
+
+        let mut encoder = png::Encoder::new(
+            writer,
+            self.pixels[0].len() as u32,
+            self.pixels.len() as u32,
+        );
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&indexes)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Encode the screen as an RGB PNG, with the colors of `write_rgb`
+    #[cfg(feature = "png")]
+    pub fn write_png<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut rgb = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+        self.write_rgb(&mut rgb)
+            .expect("the buffer is sized for the screen");
+
+        let mut encoder = png::Encoder::new(writer, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&rgb)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Decode the palette indexes into packed RGB24, one row after another.
+    /// The output must be exactly `width * height * 3` bytes, so frontends
+    /// find out about a size change instead of drawing garbage.
+    pub fn write_rgb(&self, out: &mut [u8]) -> Result<(), ScreenError> {
+        self.write_rgb_scaled(out, 1)
+    }
+
+    /// Like `write_rgb`, repeating every pixel `scale` times across and down for a
+    /// nearest neighbor upscale. The output must be exactly
+    /// `width * height * scale * scale * 3` bytes.
+    pub fn write_rgb_scaled(&self, out: &mut [u8], scale: usize) -> Result<(), ScreenError> {
+        let width = self.pixels[0].len();
+        let height = self.pixels.len();
+        let expected = width * height * scale * scale * 3;
+
+        if out.len() != expected {
+            return Err(ScreenError::DimensionMismatch {
+                expected,
+                actual: out.len(),
+            });
+        }
+        if scale == 0 {
+            return Ok(());
+        }
+
+        // decode each row once, then copy it to the rows below
+        let row_size = width * scale * 3;
+        for (row, out_rows) in self
+            .pixels
+            .iter()
+            .zip(out.chunks_exact_mut(row_size * scale))
+        {
+            let (first, rest) = out_rows.split_at_mut(row_size);
+            for (px, palette_color) in first.chunks_exact_mut(scale * 3).zip(row) {
+                let rgb = to_rgb(*palette_color);
+                for copy in px.chunks_exact_mut(3) {
+                    copy.copy_from_slice(&rgb);
+                }
+            }
+            for copy in rest.chunks_exact_mut(row_size) {
+                copy.copy_from_slice(first);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// the RGB of a pixel, with emphasis applied
+fn to_rgb(palette_color: u16) -> [u8; 3] {
+    let [_, mut r, mut g, mut b] = PALETTE_RGB[(palette_color & 0x3f) as usize].to_be_bytes();
+    if palette_color & EMPHASIS_RED != 0 {
+        (g, b) = (attenuate(g), attenuate(b));
+    }
+    if palette_color & EMPHASIS_GREEN != 0 {
+        (r, b) = (attenuate(r), attenuate(b));
+    }
+    if palette_color & EMPHASIS_BLUE != 0 {
+        (r, g) = (attenuate(r), attenuate(g));
+    }
+    [r, g, b]
 }
 
 #[derive(Clone)]
@@ -213,14 +487,34 @@ pub(crate) struct PPU {
     cycle_in_scanline: u16, // 0..=340
     scanline: u16,          // 0..=261
     frame: usize,
+    // set when the frame wraps, and cleared by the console
+    pub(crate) frame_complete: bool,
+    // configuration rather than state: emulate the shorter odd frames of NTSC
+    odd_frame_skip: bool,
+    // configuration: translate colors from the Vs. System palette
+    vs_palette: bool,
+    // configuration: the PAL PPU wires PPUMASK bits 5 and 6 to green and red
+    pal_emphasis: bool,
+    // configuration: debug views hiding a layer on top of PPUMASK, without affecting sprite 0 hits
+    show_background_layer: bool,
+    show_sprite_layer: bool,
+    // configuration: emulate quirks that well behaved games never rely on
+    strict_accuracy: bool,
+    // configuration: frames until a bit of the open bus nobody drives fades to 0, None to keep it
+    open_bus_decay: Option<u32>,
     control_reg: u8,
     status_reg: u8,
     mask_reg: u8,
     oam_addr: u8,
     buffered_ppu_data: Cell<u8>,
+    // the data bus between the CPU and PPU holds the last value transferred
+    // https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+    pub(crate) open_bus: Cell<u8>,
+    // the frame each bit of the open bus was last driven, for decay
+    pub(crate) open_bus_refreshed: Cell<[usize; 8]>,
     v: u16,
     t: u16,
-    w: bool,
+    w: Cell<bool>,
     pub(crate) in_vblank: bool,
     fine_x: u8,
     oam: [u8; 256],
@@ -232,6 +526,12 @@ pub(crate) struct PPU {
     processed_tile: [TileData; 2],
     processed_sprites: [ProcessedSprite; 8],
     sprite_zero_in_line: bool,
+    // (scanline, dot) where the sprite zero hit flag was set this frame, for debugging splits
+    sprite_zero_hit_at: Option<(u16, u16)>,
+    // $2002 reads on the visible scanlines of this frame, to spot waits for a sprite zero hit
+    // that never comes. not saved
+    status_polls: u32,
+    sprite_zero_stall: bool,
     pub(crate) last_read: Cell<Option<u16>>,
 }
 
@@ -241,11 +541,21 @@ impl Default for PPU {
             cycle_in_scanline: Default::default(),
             scanline: Default::default(),
             frame: Default::default(),
+            frame_complete: Default::default(),
+            odd_frame_skip: true,
+            vs_palette: false,
+            pal_emphasis: false,
+            show_background_layer: true,
+            show_sprite_layer: true,
+            strict_accuracy: true,
+            open_bus_decay: None,
             control_reg: Default::default(),
             status_reg: Default::default(),
             mask_reg: Default::default(),
             oam_addr: Default::default(),
             buffered_ppu_data: Default::default(),
+            open_bus: Default::default(),
+            open_bus_refreshed: Default::default(),
             v: Default::default(),
             t: Default::default(),
             w: Default::default(),
@@ -260,6 +570,9 @@ impl Default for PPU {
             processed_tile: Default::default(),
             processed_sprites: Default::default(),
             sprite_zero_in_line: Default::default(),
+            sprite_zero_hit_at: None,
+            status_polls: 0,
+            sprite_zero_stall: false,
             last_read: Default::default(),
         }
     }
@@ -285,6 +598,116 @@ impl PPU {
         self.last_read.set(None);
     }
 
+    // back to the power-on state for another cartridge, keeping the configuration
+    pub(crate) fn power_on(&mut self) {
+        *self = PPU {
+            odd_frame_skip: self.odd_frame_skip,
+            vs_palette: self.vs_palette,
+            pal_emphasis: self.pal_emphasis,
+            show_background_layer: self.show_background_layer,
+            show_sprite_layer: self.show_sprite_layer,
+            strict_accuracy: self.strict_accuracy,
+            open_bus_decay: self.open_bus_decay,
+            ..PPU::default()
+        };
+    }
+
+    // the reset button clears the registers, but memory and the frame timing carry on
+    // https://www.nesdev.org/wiki/PPU_power_up_state
+    pub(crate) fn soft_reset(&mut self) {
+        self.control_reg = 0;
+        self.mask_reg = 0;
+        self.w.set(false);
+        self.buffered_ppu_data.set(0);
+        self.pending_nmi = false;
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.cycle_in_scanline);
+        state.write_u16(self.scanline);
+        state.write_u64(self.frame as u64);
+        state.write_u8(self.control_reg);
+        state.write_u8(self.status_reg);
+        state.write_u8(self.mask_reg);
+        state.write_u8(self.oam_addr);
+        state.write_u8(self.buffered_ppu_data.get());
+        state.write_u8(self.open_bus.get());
+        for frame in self.open_bus_refreshed.get() {
+            state.write_u64(frame as u64);
+        }
+        state.write_u16(self.v);
+        state.write_u16(self.t);
+        state.write_bool(self.w.get());
+        state.write_bool(self.in_vblank);
+        state.write_u8(self.fine_x);
+        state.write_bytes(&self.oam);
+        state.write_bytes(&self.secondary_oam);
+        state.write_bytes(&self.palette_ram);
+        state.write_bytes(&self.nametables);
+        state.write_bool(self.pending_nmi);
+        self.pending_tile.save_state(state);
+        self.processed_tile
+            .iter()
+            .for_each(|tile| tile.save_state(state));
+        for processed_sprite in &self.processed_sprites {
+            state.write_bytes(&<[u8; 4]>::from(&processed_sprite.sprite));
+            processed_sprite.tile.save_state(state);
+        }
+        state.write_bool(self.sprite_zero_in_line);
+        state.write_bool(self.last_read.get().is_some());
+        state.write_u16(self.last_read.get().unwrap_or_default());
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), LoadStateError> {
+        self.cycle_in_scanline = state.read_u16()?;
+        self.scanline = state.read_u16()?;
+        self.frame = state.read_u64()? as usize;
+        self.control_reg = state.read_u8()?;
+        self.status_reg = state.read_u8()?;
+        self.mask_reg = state.read_u8()?;
+        self.oam_addr = state.read_u8()?;
+        self.buffered_ppu_data.set(state.read_u8()?);
+        self.open_bus.set(state.read_u8()?);
+        let mut open_bus_refreshed = [0; 8];
+        for frame in &mut open_bus_refreshed {
+            *frame = state.read_u64()? as usize;
+        }
+        self.open_bus_refreshed.set(open_bus_refreshed);
+        self.v = state.read_u16()?;
+        self.t = state.read_u16()?;
+        self.w.set(state.read_bool()?);
+        self.in_vblank = state.read_bool()?;
+        self.fine_x = state.read_u8()?;
+        state.read_bytes(&mut self.oam)?;
+        state.read_bytes(&mut self.secondary_oam)?;
+        state.read_bytes(&mut self.palette_ram)?;
+        if self.palette_ram.iter().any(|color| *color > 0x3f) {
+            return Err(LoadStateError::Corrupt);
+        }
+        state.read_bytes(&mut self.nametables)?;
+        self.pending_nmi = state.read_bool()?;
+        self.pending_tile.load_state(state)?;
+        for tile in &mut self.processed_tile {
+            tile.load_state(state)?;
+        }
+        for processed_sprite in &mut self.processed_sprites {
+            let mut raw_sprite = [0u8; 4];
+            state.read_bytes(&mut raw_sprite)?;
+            processed_sprite.sprite = ParsedSprite::from(&raw_sprite);
+            processed_sprite.tile.load_state(state)?;
+        }
+        self.sprite_zero_in_line = state.read_bool()?;
+        let has_last_read = state.read_bool()?;
+        let last_read = state.read_u16()?;
+        self.last_read.set(has_last_read.then_some(last_read));
+
+        if self.cycle_in_scanline > 340 || self.scanline > 261 || self.fine_x > 7 {
+            return Err(LoadStateError::Corrupt);
+        }
+
+        Ok(())
+    }
+
     fn multiplex_colors(
         tile_palette: u8,
         tile_palette_offset: u8,
@@ -323,19 +746,30 @@ impl PPU {
         (multiplexer_decision, colors[multiplexer_decision as usize])
     }
 
+    // the visible scanlines and the pre-render scanline
+    fn rendering_line(&self) -> bool {
+        self.scanline < 240 || self.scanline == 261
+    }
+
     fn rendering_enabled(&self) -> bool {
         let parsed_mask = PPUMask::from(self.mask_reg);
         return parsed_mask.show_background || parsed_mask.show_sprites;
     }
 
-    pub(crate) fn step(&mut self, mapper: &dyn Mapper, screen: &mut Screen) {
+    /// Advance one dot. Pixels are drawn straight into `screen`, which belongs to the caller,
+    /// so a frame is complete in it once `frame_complete` is set.
+    pub(crate) fn step(&mut self, mapper: &mut dyn Mapper, screen: &mut Screen) {
         // change signals on the next cycle
         match self.last_read.get() {
             Some(0x2002) => {
-                self.w = false;
                 self.status_reg &= !0b1000_0000; // NMI occurred
+                if self.scanline < 240 && self.rendering_enabled() {
+                    self.status_polls += 1;
+                }
             }
             Some(0x2007) => {
+                // the read put v on the address bus
+                mapper.notify_chr_fetch(self.v & 0x3fff);
                 self.v = self
                     .v
                     .wrapping_add(if PPUControl::from(self.control_reg).vram_increment {
@@ -360,16 +794,114 @@ impl PPU {
         self.update_cycle();
     }
 
+    #[cfg(any(test, feature = "debug"))]
+    pub(crate) fn debug_set_scroll(&mut self, v: u16, t: u16, fine_x: u8) {
+        self.v = v & 0x7fff;
+        self.t = t & 0x7fff;
+        self.fine_x = fine_x & 0b111;
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    pub(crate) fn debug_set_registers(&mut self, control: u8, mask: u8) {
+        self.control_reg = control;
+        self.mask_reg = mask;
+    }
+
+    pub(crate) fn set_odd_frame_skip(&mut self, enabled: bool) {
+        self.odd_frame_skip = enabled;
+    }
+
+    pub(crate) fn set_vs_palette(&mut self, enabled: bool) {
+        self.vs_palette = enabled;
+    }
+
+    pub(crate) fn set_pal_emphasis(&mut self, enabled: bool) {
+        self.pal_emphasis = enabled;
+    }
+
+    pub(crate) fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.show_background_layer = background;
+        self.show_sprite_layer = sprites;
+    }
+
+    // carry the debug overrides and the accuracy settings over to a PPU restored from a rewind
+    // or snapshot, since they belong to the frontend rather than to the emulated state
+    pub(crate) fn keep_debug_settings(&mut self, from: &PPU) {
+        self.strict_accuracy = from.strict_accuracy;
+        self.odd_frame_skip = from.odd_frame_skip;
+        self.pal_emphasis = from.pal_emphasis;
+        self.vs_palette = from.vs_palette;
+        self.show_background_layer = from.show_background_layer;
+        self.show_sprite_layer = from.show_sprite_layer;
+        self.open_bus_decay = from.open_bus_decay;
+    }
+
+    pub(crate) fn set_strict_accuracy(&mut self, enabled: bool) {
+        self.strict_accuracy = enabled;
+    }
+
+    pub(crate) fn strict_accuracy(&self) -> bool {
+        self.strict_accuracy
+    }
+
+    pub(crate) fn set_open_bus_decay(&mut self, frames: Option<u32>) {
+        self.open_bus_decay = frames;
+    }
+
+    // (scanline, dot) that will be rendered by the next step
+    pub(crate) fn position(&self) -> (u16, u16) {
+        (self.scanline, self.cycle_in_scanline)
+    }
+
+    pub(crate) fn scroll(&self) -> ScrollInfo {
+        let v = VRAMAddress::from(self.v);
+        ScrollInfo {
+            coarse_x: v.coarse_x,
+            coarse_y: v.coarse_y,
+            fine_x: self.fine_x,
+            fine_y: v.fine_y,
+            nametable: v.nametable,
+        }
+    }
+
+    pub(crate) fn sprite_zero_hit_at(&self) -> Option<(u16, u16)> {
+        self.sprite_zero_hit_at
+    }
+
+    // whether a frame ended with the CPU waiting on a sprite zero hit that never happened,
+    // reported once
+    pub(crate) fn take_sprite_zero_stall(&mut self) -> bool {
+        core::mem::take(&mut self.sprite_zero_stall)
+    }
+
+    pub(crate) fn frame(&self) -> u64 {
+        self.frame as u64
+    }
+
+    // (background, sprites) pattern tables selected by PPUCTRL. 8x16 sprites ignore theirs
+    pub(crate) fn pattern_table_bases(&self) -> (u16, u16) {
+        let ppu_control = PPUControl::from(self.control_reg);
+        (
+            (ppu_control.background_pattern_table as u16) << 12,
+            (ppu_control.sprite_pattern_table as u16) << 12,
+        )
+    }
+
+    // sprites are always 8 pixels wide, and 8 or 16 pixels tall
+    pub(crate) fn sprite_height(&self) -> u8 {
+        if PPUControl::from(self.control_reg).tall_sprites {
+            16
+        } else {
+            8
+        }
+    }
+
     fn find_sprites_in_line(&mut self) {
         // Cycles 1-64: fill secondary OAM with 0xFF
         // Timing ultimately doesn't matter for accuracy because it's internal to sprite evaluation
         self.secondary_oam.fill(0xff);
 
-        let sprite_height = if PPUControl::from(self.control_reg).tall_sprites {
-            16
-        } else {
-            8
-        };
+        let sprite_height = self.sprite_height() as u16;
 
         let mut overflow = false;
         let mut sprite_count: u8 = 0;
@@ -400,7 +932,7 @@ impl PPU {
             }
         }
 
-        self.status_reg &= 1 << 5;
+        self.status_reg &= !(1 << 5);
         self.status_reg |= (overflow as u8) << 5;
     }
 
@@ -411,7 +943,33 @@ impl PPU {
         // retrieve the background tile
         let fine_x = (x % 8) as u8 + self.fine_x;
         let tile = &self.processed_tile[(fine_x >= 8) as usize];
-        let tile_palette = tile.color(fine_x % 8);
+        let (color, sprite_zero_overlap) =
+            self.composite_pixel(tile, fine_x % 8, &self.processed_sprites, x);
+
+        // https://www.nesdev.org/wiki/PPU_OAM#Sprite_zero_hits
+        // an opaque pixel of sprite 0 overlaps an opaque background pixel, regardless of priority.
+        // the vertical extent (8 or 16 rows) was already handled by sprite evaluation
+        let zero_hit = self.sprite_zero_in_line && sprite_zero_overlap;
+
+        // set the sprite zero hit bit
+        if zero_hit && self.status_reg & 0b0100_0000 == 0 {
+            self.sprite_zero_hit_at = Some((y, self.cycle_in_scanline));
+        }
+        self.status_reg |= (zero_hit as u8) << 6;
+
+        screen.pixels[y as usize][x as usize] = self.output_color(color);
+    }
+
+    // the palette RAM offset shown at column `x` over pixel `tile_x` of the background tile,
+    // and whether the first of `sprites` is opaque there on top of an opaque background
+    fn composite_pixel(
+        &self,
+        tile: &TileData,
+        tile_x: u8,
+        sprites: &[ProcessedSprite],
+        x: u16,
+    ) -> (u8, bool) {
+        let tile_palette = tile.color(tile_x);
         let tile_palette_offset = (tile.palette & 0x3) << 2;
 
         // retrieve the matching sprite
@@ -420,8 +978,8 @@ impl PPU {
         let mut sprite_palette_offset: u8 = 0;
         let mut sprite_in_background: bool = false;
 
-        if PPUMask::from(self.control_reg).show_sprites {
-            for (idx, processed_sprite) in self.processed_sprites.iter().enumerate() {
+        if PPUMask::from(self.mask_reg).show_sprites {
+            for (idx, processed_sprite) in sprites.iter().enumerate() {
                 if processed_sprite.sprite.is_empty() {
                     break;
                 }
@@ -441,26 +999,72 @@ impl PPU {
             }
         }
 
-        let (decision, color) = PPU::multiplex_colors(
-            tile_palette,
+        // hidden layers are transparent to the multiplexer, but still count for sprite 0 hits
+        let (_, color) = PPU::multiplex_colors(
+            if self.show_background_layer {
+                tile_palette
+            } else {
+                0
+            },
             tile_palette_offset,
-            sprite_palette,
+            if self.show_sprite_layer {
+                sprite_palette
+            } else {
+                0
+            },
             0x10 | sprite_palette_offset,
             sprite_in_background,
         );
-        let zero_hit = self.sprite_zero_in_line
-            && sprite_pos == 0
-            && decision == MultiplexerDecision::DrawSprite;
 
-        // set the sprite zero hit bit
-        self.status_reg |= (zero_hit as u8) << 6;
+        (
+            color,
+            sprite_pos == 0 && sprite_palette != 0 && tile_palette != 0,
+        )
+    }
 
-        screen.pixels[y as usize][x as usize] =
-            self.palette_ram[PPU::mirror_palette(color) as usize];
+    // https://www.nesdev.org/wiki/PPU_palettes#The_background_palette_hack
+    // with rendering disabled the PPU outputs the backdrop color, unless v points into
+    // palette RAM, in which case that entry is output instead
+    fn render_backdrop(&mut self, screen: &mut Screen) {
+        let x = self.cycle_in_scanline - 1;
+        let y = self.scanline;
+
+        screen.pixels[y as usize][x as usize] = self.output_color(self.backdrop_color());
+    }
+
+    fn backdrop_color(&self) -> u8 {
+        if self.v & 0x3f00 == 0x3f00 {
+            (self.v % 0x20) as u8
+        } else {
+            0
+        }
+    }
+
+    // palette RAM offset to the pixel stored in Screen
+    fn output_color(&self, color: u8) -> u16 {
+        // greyscale keeps only the brightness of the color, then emphasis applies on top
+        // https://www.nesdev.org/wiki/PPU_registers#Color_effects
+        let mut palette_color = self.palette_ram[PPU::mirror_palette(color) as usize] as u16;
+        if PPUMask::from(self.mask_reg).greyscale {
+            palette_color &= 0x30;
+        }
+        if self.vs_palette {
+            palette_color = VS_PALETTE[palette_color as usize] as u16;
+        }
+
+        // the screen always stores emphasis in red, green, blue order
+        let mut emphasis = self.mask_reg as u16 >> 5;
+        if self.pal_emphasis {
+            emphasis = emphasis & 0b100 | (emphasis & 0b01) << 1 | (emphasis & 0b10) >> 1;
+        }
+        palette_color | emphasis << 6
     }
 
-    fn step_visible(&mut self, mapper: &dyn Mapper, screen: &mut Screen) {
+    fn step_visible(&mut self, mapper: &mut dyn Mapper, screen: &mut Screen) {
         if !self.rendering_enabled() {
+            if let 1..=256 = self.cycle_in_scanline {
+                self.render_backdrop(screen);
+            }
             return;
         }
 
@@ -479,8 +1083,6 @@ impl PPU {
                 // TODO: mapper.on_scanline();
             }
             320 => {
-                let ppu_control = PPUControl::from(self.control_reg);
-                let sprite_height: u8 = if ppu_control.tall_sprites { 16 } else { 8 };
                 let y = self.scanline;
 
                 // Cycles 257-320: Sprite fetches (8 sprites total, 8 cycles per sprite).
@@ -490,46 +1092,32 @@ impl PPU {
                 // For the first empty sprite slot, this will consist of sprite #63's Y-coordinate followed by 3 $FF bytes; for subsequent empty sprite slots, this will be four $FF bytes
                 for (idx, raw_sprite) in self.secondary_oam.chunks_exact(4).enumerate() {
                     let raw_sprite: &[u8; 4] = raw_sprite.try_into().unwrap();
-                    let processed_sprite = &mut self.processed_sprites[idx];
-                    processed_sprite.sprite = ParsedSprite::from(raw_sprite);
+                    let sprite = ParsedSprite::from(raw_sprite);
 
-                    // continue if the sprite is empty
+                    // empty sprites still fetch tile $FF, which mappers watching the address bus see
                     if raw_sprite == &[0xff; 4] {
+                        let pattern_table_address = self.sprite_pattern_table(sprite.tile_index);
+                        mapper.notify_chr_fetch(pattern_table_address | 0x0ff0);
+                        mapper.notify_chr_fetch(pattern_table_address | 0x0ff8);
+                        self.processed_sprites[idx].sprite = sprite;
                         continue;
                     }
 
                     // retrieve the corresponding tile
-                    let bank = if ppu_control.tall_sprites {
-                        processed_sprite.sprite.tile_index & 0b1
-                    } else {
-                        ppu_control.sprite_pattern_table as u8
-                    };
-
-                    let pattern_table_address = (bank as u16) << 12;
-                    let mut tile_index =
-                        processed_sprite.sprite.tile_index & !(ppu_control.tall_sprites as u8);
-                    let mut tile_y = (y - (processed_sprite.sprite.top_y as u16)) as u8;
-
-                    tile_y = if processed_sprite.sprite.flip_vertical {
-                        sprite_height - 1 - tile_y
-                    } else {
-                        tile_y
-                    };
-
-                    tile_index &= !(ppu_control.tall_sprites as u8);
-                    tile_index += (tile_y >= 8) as u8;
-                    tile_y &= 0x7;
-
-                    let tile_address_lo =
-                        pattern_table_address | (tile_index as u16) << 4 | (0 << 3) | tile_y as u16;
+                    let tile_address_lo = self.sprite_row_address(&sprite, y);
                     let tile_address_hi = tile_address_lo | (1 << 3);
-
-                    processed_sprite.tile = TileData {
-                        nametable_index: 0,
-                        palette: processed_sprite.sprite.palette,
-                        pattern_low: mapper.read(tile_address_lo),
-                        pattern_high: mapper.read(tile_address_hi),
-                    }
+                    mapper.notify_chr_fetch(tile_address_lo);
+                    mapper.notify_chr_fetch(tile_address_hi);
+
+                    self.processed_sprites[idx] = ProcessedSprite {
+                        tile: TileData {
+                            nametable_index: 0,
+                            palette: sprite.palette,
+                            pattern_low: mapper.read(tile_address_lo),
+                            pattern_high: mapper.read(tile_address_hi),
+                        },
+                        sprite,
+                    };
                 }
             }
             321..=336 => {
@@ -544,6 +1132,38 @@ impl PPU {
         self.update_vram_addr();
     }
 
+    fn sprite_pattern_table(&self, tile_index: u8) -> u16 {
+        let ppu_control = PPUControl::from(self.control_reg);
+        let bank = if ppu_control.tall_sprites {
+            tile_index & 0b1
+        } else {
+            ppu_control.sprite_pattern_table as u8
+        };
+        (bank as u16) << 12
+    }
+
+    // the pattern address of the low plane of the row of `sprite` that was evaluated on
+    // scanline `y`, and is drawn on the one after it
+    fn sprite_row_address(&self, sprite: &ParsedSprite, y: u16) -> u16 {
+        let tall_sprites = PPUControl::from(self.control_reg).tall_sprites;
+        let mut tile_index = sprite.tile_index & !(tall_sprites as u8);
+        let mut tile_y = (y - (sprite.top_y as u16)) as u8;
+
+        tile_y = if sprite.flip_vertical {
+            self.sprite_height() - 1 - tile_y
+        } else {
+            tile_y
+        };
+
+        tile_index += (tile_y >= 8) as u8;
+        tile_y &= 0x7;
+
+        self.sprite_pattern_table(sprite.tile_index)
+            | (tile_index as u16) << 4
+            | (0 << 3)
+            | tile_y as u16
+    }
+
     fn step_post_render(&mut self, mapper: &dyn Mapper) {}
 
     fn step_vblank(&mut self, mapper: &dyn Mapper) {
@@ -555,11 +1175,21 @@ impl PPU {
         }
     }
 
-    fn step_pre_render(&mut self, mapper: &dyn Mapper) {
+    fn step_pre_render(&mut self, mapper: &mut dyn Mapper) {
         // Pre-render scanline (-1 or 261)
         if self.cycle_in_scanline == 1 {
+            // a game polling $2002 all through the picture without a hit is likely stuck
+            if self.rendering_enabled()
+                && self.sprite_zero_hit_at.is_none()
+                && self.status_polls >= SPRITE_ZERO_STALL_POLLS
+            {
+                self.sprite_zero_stall = true;
+            }
+            self.status_polls = 0;
+
             // disable sprite zero hit + nmi occurred
             self.status_reg &= !0b1100_0000;
+            self.sprite_zero_hit_at = None;
             self.in_vblank = false;
             self.pending_nmi = false;
         }
@@ -579,23 +1209,20 @@ impl PPU {
         self.update_vram_addr();
     }
 
-    fn fetch_background_tile(&mut self, mapper: &dyn Mapper) {
+    fn fetch_background_tile(&mut self, mapper: &mut dyn Mapper) {
         // https://www.nesdev.org/wiki/PPU_scrolling#Tile_and_attribute_fetching
         match self.cycle_in_scanline % 8 {
             0 => self.processed_tile = [self.processed_tile[1].clone(), self.pending_tile.clone()],
             1 => {
                 let nametable_addr = 0x2000 | (self.v & 0x0FFF);
-                self.pending_tile.nametable_index = self.read_byte(mapper, nametable_addr)
+                self.pending_tile.nametable_index = self.fetch_byte(mapper, nametable_addr)
             }
             2 => {}
             3 => {
                 // https://www.nesdev.org/wiki/PPU_scrolling#Tile_and_attribute_fetching
                 // https://www.nesdev.org/wiki/PPU_attribute_tables
-                let attr_address =
-                    0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
-                let attr_data = self.read_byte(mapper, attr_address);
-                let attr_shift = (self.v & 0x40) >> 4 | (self.v & 0x2);
-                self.pending_tile.palette = (attr_data >> attr_shift) & 0b11;
+                let attr_data = self.fetch_byte(mapper, PPU::attribute_address(self.v));
+                self.pending_tile.palette = PPU::attribute_palette(self.v, attr_data);
             }
             4 => {}
             5 => {
@@ -605,13 +1232,9 @@ impl PPU {
                 //      ^^^^ ^^^^ ------- tile
                 //                0------ low byte
                 //    ^ ---- ---- ------- foreground/background
-                let pattern_table =
-                    (PPUControl::from(self.control_reg).background_pattern_table as u16) << 12;
-                let nametable_index = (self.pending_tile.nametable_index as u16) << 4;
-                let lo_byte_offset = 0 << 3;
-                let fine_y = VRAMAddress::from(self.v).fine_y as u16;
-                let pattern_low_address = pattern_table | nametable_index | lo_byte_offset | fine_y;
-                self.pending_tile.pattern_low = self.read_byte(mapper, pattern_low_address);
+                let pattern_low_address =
+                    self.background_pattern_address(self.pending_tile.nametable_index, self.v);
+                self.pending_tile.pattern_low = self.fetch_byte(mapper, pattern_low_address);
             }
             6 => {}
             7 => {
@@ -621,19 +1244,112 @@ impl PPU {
                 //      ^^^^ ^^^^ ------- tile
                 //                1------ high byte
                 //    ^ ---- ---- ------- foreground/background
-                let pattern_table =
-                    (PPUControl::from(self.control_reg).background_pattern_table as u16) << 12;
-                let nametable_index = (self.pending_tile.nametable_index as u16) << 4;
-                let hi_byte_offset = 1 << 3;
-                let fine_y = VRAMAddress::from(self.v).fine_y as u16;
-                let pattern_high_address =
-                    pattern_table | nametable_index | hi_byte_offset | fine_y;
-                self.pending_tile.pattern_high = self.read_byte(mapper, pattern_high_address);
+                let pattern_high_address = self
+                    .background_pattern_address(self.pending_tile.nametable_index, self.v)
+                    | (1 << 3);
+                self.pending_tile.pattern_high = self.fetch_byte(mapper, pattern_high_address);
             }
             _ => unreachable!(),
         };
     }
 
+    fn attribute_address(v: u16) -> u16 {
+        0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07)
+    }
+
+    // the 2 bits of the attribute byte covering the quadrant v is in
+    fn attribute_palette(v: u16, attr_data: u8) -> u8 {
+        let attr_shift = (v & 0x40) >> 4 | (v & 0x2);
+        (attr_data >> attr_shift) & 0b11
+    }
+
+    // the address of the low plane, the high plane follows 8 bytes later
+    fn background_pattern_address(&self, nametable_index: u8, v: u16) -> u16 {
+        let pattern_table =
+            (PPUControl::from(self.control_reg).background_pattern_table as u16) << 12;
+        let fine_y = VRAMAddress::from(v).fine_y as u16;
+        pattern_table | (nametable_index as u16) << 4 | fine_y
+    }
+
+    // the background tile at v, read like fetch_background_tile but without the mapper
+    // seeing the fetches
+    fn peek_background_tile(&self, mapper: &dyn Mapper, v: u16) -> TileData {
+        let nametable_index = self.read_byte(mapper, 0x2000 | (v & 0x0FFF));
+        let attr_data = self.read_byte(mapper, PPU::attribute_address(v));
+        let pattern_low_address = self.background_pattern_address(nametable_index, v);
+        TileData {
+            nametable_index,
+            palette: PPU::attribute_palette(v, attr_data),
+            pattern_low: self.read_byte(mapper, pattern_low_address),
+            pattern_high: self.read_byte(mapper, pattern_low_address | (1 << 3)),
+        }
+    }
+
+    /// Composite scanline `y` the way the next frame would draw it with the current VRAM, OAM,
+    /// palette, PPUCTRL/PPUMASK and scroll register `t`, as palette indexes. Nothing is changed:
+    /// no status flags are set and the mapper doesn't see the fetches.
+    pub(crate) fn preview_scanline(&self, mapper: &dyn Mapper, y: u16) -> [u8; SCREEN_WIDTH] {
+        if !self.rendering_enabled() {
+            return [(self.output_color(self.backdrop_color()) & 0x3f) as u8; SCREEN_WIDTH];
+        }
+
+        // v is reloaded from t on the pre-render scanline, then moves down a row every scanline
+        let mut parsed_addr = VRAMAddress::from(self.t);
+        for _ in 0..y {
+            parsed_addr.increment_y();
+        }
+        let mut v: u16 = parsed_addr.into();
+
+        // fine X can push the line into a 33rd tile
+        let tiles: [TileData; 33] = core::array::from_fn(|_| {
+            let tile = self.peek_background_tile(mapper, v);
+            let mut parsed_addr = VRAMAddress::from(v);
+            parsed_addr.increment_x();
+            v = parsed_addr.into();
+            tile
+        });
+
+        // sprites are evaluated on the scanline above the one they're drawn on
+        let mut sprites: [ProcessedSprite; 8] = Default::default();
+        let mut sprite_count = 0;
+        if let Some(sprite_y) = y.checked_sub(1) {
+            let sprite_height = self.sprite_height() as u16;
+            for raw_sprite in self.oam.chunks_exact(4) {
+                let sprite = ParsedSprite::from(<&[u8; 4]>::try_from(raw_sprite).unwrap());
+                let top_y = sprite.top_y as u16;
+                if sprite_count == sprites.len()
+                    || sprite_y < top_y
+                    || sprite_y >= top_y + sprite_height
+                {
+                    continue;
+                }
+
+                let tile_address_lo = self.sprite_row_address(&sprite, sprite_y);
+                sprites[sprite_count] = ProcessedSprite {
+                    tile: TileData {
+                        nametable_index: 0,
+                        palette: sprite.palette,
+                        pattern_low: mapper.read(tile_address_lo),
+                        pattern_high: mapper.read(tile_address_lo | (1 << 3)),
+                    },
+                    sprite,
+                };
+                sprite_count += 1;
+            }
+        }
+
+        core::array::from_fn(|x| {
+            let fine_x = x + self.fine_x as usize;
+            let (color, _) = self.composite_pixel(
+                &tiles[fine_x / 8],
+                (fine_x % 8) as u8,
+                &sprites[..sprite_count],
+                x as u16,
+            );
+            (self.output_color(color) & 0x3f) as u8
+        })
+    }
+
     fn update_vram_addr(&mut self) {
         if !self.rendering_enabled() {
             return;
@@ -678,7 +1394,22 @@ impl PPU {
     }
 
     fn update_cycle(&mut self) {
-        if self.cycle_in_scanline < 340 {
+        // https://www.nesdev.org/wiki/PPU_frame_timing#Even/Odd_Frames
+        // https://www.nesdev.org/wiki/File:Ntsc_timing.png
+        // odd frames with rendering enabled are a dot shorter: the PPU jumps from dot 339 of the
+        // pre-render scanline straight to (0, 0). rendering is sampled at dot 339, so toggling
+        // it afterwards doesn't change the length of the frame
+        let last_cycle = if self.scanline == 261
+            && self.odd_frame_skip
+            && self.rendering_enabled()
+            && self.frame.wrapping_add(1) % 2 == 1
+        {
+            339
+        } else {
+            340
+        };
+
+        if self.cycle_in_scanline < last_cycle {
             // advance in current scanline
             self.cycle_in_scanline += 1;
         } else if self.scanline < 261 {
@@ -688,12 +1419,10 @@ impl PPU {
         } else {
             // move to next frame
             self.frame = self.frame.wrapping_add(1);
+            self.frame_complete = true;
+            self.decay_open_bus();
             self.scanline = 0;
-
-            // https://www.nesdev.org/wiki/PPU_frame_timing#Even/Odd_Frames
-            // https://www.nesdev.org/wiki/File:Ntsc_timing.png
-            // skip the first cycle of a frame when odd + rendering enabled
-            self.cycle_in_scanline = (self.rendering_enabled() && (self.frame % 2 == 1)) as u16;
+            self.cycle_in_scanline = 0;
         }
     }
 
@@ -733,30 +1462,40 @@ impl PPU {
         }
     }
 
-    pub(crate) fn write_dma(&mut self, page: Option<&[u8; 256]>) {
-        match page {
-            Some(page) => {
-                if self.oam_addr == 0 {
-                    self.oam.copy_from_slice(page);
-                } else {
-                    // not ideal but manageable:
-                    // oam addr isn't perfectly aligned, perform two separate memcpys
-                    let (before, after) = page.split_at(page.len() - (self.oam_addr as usize));
-                    self.oam[self.oam_addr as usize..].copy_from_slice(before);
-                    self.oam[..self.oam_addr as usize].copy_from_slice(after);
-                }
-            }
-            None => self.oam.fill(0x00),
+    pub(crate) fn write_dma(&mut self, page: &[u8; 256]) {
+        if self.oam_addr == 0 {
+            self.oam.copy_from_slice(page);
+        } else {
+            // not ideal but manageable:
+            // oam addr isn't perfectly aligned, perform two separate memcpys
+            let (before, after) = page.split_at(page.len() - (self.oam_addr as usize));
+            self.oam[self.oam_addr as usize..].copy_from_slice(before);
+            self.oam[..self.oam_addr as usize].copy_from_slice(after);
         }
     }
 
+    // a DMC fetch taking the last get of an OAM DMA leaves its sample byte on the data bus,
+    // which the last put writes in place of the last byte copied
+    pub(crate) fn corrupt_dma_last_byte(&mut self, data: u8) {
+        self.oam[self.oam_addr.wrapping_sub(1) as usize] = data;
+    }
+
+    // rendering fetches drive the address bus, which mappers like MMC3 watch for A12 edges
+    fn fetch_byte(&self, mapper: &mut dyn Mapper, addr: u16) -> u8 {
+        mapper.notify_chr_fetch(addr);
+        self.read_byte(mapper, addr)
+    }
+
     pub(crate) fn write_byte(&mut self, mapper: &mut dyn Mapper, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1fff => mapper.write(addr, data),
             0x2000..=0x3eff => {
                 self.nametables[PPU::mirror_nametable(addr, mapper.mirror()) as usize] = data;
             }
-            0x3f00.. => self.palette_ram[PPU::mirror_palette((addr % 0x20) as u8) as usize] = data,
+            // palette entries are 6 bits wide, so every stored color is a valid index
+            0x3f00.. => {
+                self.palette_ram[PPU::mirror_palette((addr % 0x20) as u8) as usize] = data & 0x3f;
+            }
         }
     }
 
@@ -768,14 +1507,60 @@ impl PPU {
         status
     }
 
+    // latch `data` on the bus, refreshing the `driven` bits against decay
+    fn drive_open_bus(&self, data: u8, driven: u8) {
+        self.open_bus.set(data);
+
+        let mut refreshed = self.open_bus_refreshed.get();
+        for (bit, frame) in refreshed.iter_mut().enumerate() {
+            if driven & (1 << bit) != 0 {
+                *frame = self.frame;
+            }
+        }
+        self.open_bus_refreshed.set(refreshed);
+    }
+
+    // the bits that nothing drove for the decay time fade to 0, checked once a frame
+    fn decay_open_bus(&mut self) {
+        let Some(decay) = self.open_bus_decay else {
+            return;
+        };
+
+        let mut open_bus = self.open_bus.get();
+        for (bit, refreshed) in self.open_bus_refreshed.get().iter().enumerate() {
+            if self.frame.wrapping_sub(*refreshed) >= decay as usize {
+                open_bus &= !(1 << bit);
+            }
+        }
+        self.open_bus.set(open_bus);
+    }
+
+    // without strict accuracy, the bits nothing drives read as 0
+    fn read_open_bus(&self) -> u8 {
+        if self.strict_accuracy {
+            self.open_bus.get()
+        } else {
+            0
+        }
+    }
+
     pub(crate) fn read_register(&self, mapper: &dyn Mapper, addr: u16) -> u8 {
         // change statuses signals on the next step()
         self.last_read.set(Some(0x2000 | (addr & 0xf)));
 
-        match 0x2000 | (addr & 0xf) {
+        // the bits this read drives on the bus, the rest are the open bus read back
+        let mut driven = 0xff;
+        let data = match 0x2000 | (addr & 0xf) {
             0x2002 => {
                 // PPUSTATUS: $2002
-                self.status_reg
+                // only the top three bits are driven, the rest come from the open bus
+                // the write latch is shared with $2005/$2006, so it's cleared right away
+                // for a write that lands before the next step()
+                self.w.set(false);
+                let mut status = PPUStatus::from(self.status_reg);
+                status.open_bus = self.read_open_bus();
+                driven = 0b1110_0000;
+                status.into()
             }
             0x2004 => {
                 // OAMDATA: $2004
@@ -800,11 +1585,20 @@ impl PPU {
 
                 contents
             }
-            _ => 0,
-        }
+            // write only registers read back the open bus
+            _ => {
+                driven = 0;
+                self.read_open_bus()
+            }
+        };
+
+        self.drive_open_bus(data, driven);
+        data
     }
 
     pub(crate) fn write_register(&mut self, mapper: &mut dyn Mapper, addr: u16, data: u8) {
+        self.drive_open_bus(data, 0xff);
+
         match 0x2000 | (addr & 0xf) {
             0x2000 => {
                 // PPUCTRL: $2000
@@ -834,15 +1628,22 @@ impl PPU {
             }
             0x2004 => {
                 // OAMDATA: $2004
-                self.oam[self.oam_addr as usize] = data;
-                self.oam_addr = self.oam_addr.wrapping_add(1);
+                // https://www.nesdev.org/wiki/PPU_registers#OAMDATA
+                // while rendering, OAM is busy with sprite evaluation: the write is dropped,
+                // and OAMADDR is bumped by a glitched increment of its high 6 bits instead
+                if self.strict_accuracy && self.rendering_enabled() && self.rendering_line() {
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    self.oam[self.oam_addr as usize] = data;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
             }
             0x2005 => {
                 // PPUSCROLL: $2005
-                if !self.w {
+                if !self.w.get() {
                     // t: ....... ...ABCDE <- d: ABCDE...
                     // x:              FGH <- d: .....FGH
-                    self.w = true;
+                    self.w.set(true);
                     self.t = {
                         let mut t = VRAMAddress::from(self.t);
                         t.coarse_x = data >> 3;
@@ -851,7 +1652,7 @@ impl PPU {
                     self.fine_x = data & 0b111;
                 } else {
                     // t: FGH..AB CDE..... <- d: ABCDEFGH
-                    self.w = false;
+                    self.w.set(false);
                     self.t = {
                         let mut t = VRAMAddress::from(self.t);
                         t.coarse_y = data >> 3;
@@ -862,23 +1663,27 @@ impl PPU {
             }
             0x2006 => {
                 // PPUADDR: $2006
-                if !self.w {
+                if !self.w.get() {
                     // t: .CDEFGH ........ <- d: ..CDEFGH
                     //        <unused>     <- d: AB......
                     // t: Z...... ........ <- 0 (bit Z is cleared)
                     let mask = 0x80ff;
-                    self.w = true;
+                    self.w.set(true);
                     self.t = self.t & mask | (data as u16) << 8 & !mask;
                 } else {
                     // t: ....... ABCDEFGH <- d: ABCDEFGH
                     // v: <...all bits...> <- t: <...all bits...>
+                    // mid-frame, the tiles fetched from here on come from the new address, and the
+                    // coarse X increments, the Y increment at dot 256 and the copy from t at dot 257
+                    // carry on from it, for a split that continues down from the written row
                     self.t = (self.t & 0xff00) | (data as u16);
                     self.v = self.t;
-                    self.w = false;
+                    self.w.set(false);
                 }
             }
             0x2007 => {
                 // PPUDATA: $2007
+                mapper.notify_chr_fetch(self.v & 0x3fff);
                 self.write_byte(mapper, self.v, data);
                 self.v = self.v.wrapping_add({
                     let vram_incr = PPUControl::from(self.control_reg).vram_increment;
@@ -893,3 +1698,626 @@ impl PPU {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Mapper, MirroringMode};
+
+    use super::{
+        decode_tile_row, Screen, ScreenError, VRAMAddress, EMPHASIS_BLUE, EMPHASIS_GREEN,
+        EMPHASIS_RED, PALETTE_RGB, PALETTE_SIZE, PPU, SCREEN_HEIGHT, SCREEN_WIDTH,
+    };
+
+    // 8 KB of CHR RAM and nothing else
+    #[derive(Clone)]
+    struct TestMapper {
+        chr: [u8; 0x2000],
+        // rising edges of A12, which clock the MMC3 scanline counter
+        a12: bool,
+        a12_rises: usize,
+    }
+
+    impl Default for TestMapper {
+        fn default() -> Self {
+            TestMapper {
+                chr: [0; 0x2000],
+                a12: false,
+                a12_rises: 0,
+            }
+        }
+    }
+
+    impl Mapper for TestMapper {
+        fn number(&self) -> u8 {
+            0
+        }
+
+        fn mirror(&self) -> MirroringMode {
+            MirroringMode::Horizontal
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            self.chr[address as usize % 0x2000]
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.chr[address as usize % 0x2000] = data;
+        }
+
+        fn read_page(&self, _page: u8) -> Option<&[u8; 256]> {
+            None
+        }
+
+        fn notify_chr_fetch(&mut self, address: u16) {
+            let a12 = address & 0x1000 != 0;
+            self.a12_rises += (!self.a12 && a12) as usize;
+            self.a12 = a12;
+        }
+    }
+
+    // step the PPU on its own, without a CPU, until the current frame is complete
+    fn render_frame(ppu: &mut PPU, mapper: &mut dyn Mapper) -> Screen {
+        let mut screen = Screen::default();
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.step(mapper, &mut screen);
+        }
+        screen
+    }
+
+    #[test]
+    fn test_decode_tile_row() {
+        // the first row of the "½" tile from the wiki's pattern table example
+        assert_eq!(
+            decode_tile_row(0b0100_0001, 0b0000_0001),
+            [0, 1, 0, 0, 0, 0, 0, 3]
+        );
+        assert_eq!(
+            decode_tile_row(0b1010_0000, 0b1100_1111),
+            [3, 2, 1, 0, 2, 2, 2, 2]
+        );
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.palette_ram[0x00] = 0x21;
+        ppu.mask_reg = 0b0000_1000; // show background
+
+        for frame in 1..=2 {
+            let screen = render_frame(&mut ppu, &mut mapper);
+            assert_eq!(ppu.frame(), frame);
+            assert_eq!(ppu.position(), (0, 0));
+            assert!(screen.pixels.iter().flatten().all(|color| *color == 0x21));
+        }
+    }
+
+    #[test]
+    fn test_forced_blank_backdrop() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.palette_ram[0x00] = 0x21;
+        ppu.palette_ram[0x05] = 0x16;
+
+        // rendering is disabled, and the stale frame is replaced by the backdrop
+        let mut screen = Screen::blank(0x0f);
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.step(&mut mapper, &mut screen);
+        }
+        assert!(screen.pixels.iter().flatten().all(|color| *color == 0x21));
+
+        // v pointing into palette RAM shows that entry instead
+        ppu.write_register(&mut mapper, 0x2006, 0x3f);
+        ppu.write_register(&mut mapper, 0x2006, 0x05);
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert!(screen.pixels.iter().flatten().all(|color| *color == 0x16));
+    }
+
+    #[test]
+    fn test_tall_sprite() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        // 8x16 sprite with tile 3: top half is tile 2, bottom half is tile 3 in the $1000 table
+        mapper.chr[0x1020..0x1028].fill(0xff); // tile 2, low plane: color 1
+        mapper.chr[0x1038..0x1040].fill(0xff); // tile 3, high plane: color 2
+        ppu.oam[..4].copy_from_slice(&[10, 0x03, 0x00, 16]);
+        ppu.palette_ram[0x00] = 0x0f;
+        ppu.palette_ram[0x11] = 0x11;
+        ppu.palette_ram[0x12] = 0x12;
+        ppu.control_reg = 0b0010_0000;
+        ppu.mask_reg = 0b0001_1110;
+        assert_eq!(ppu.sprite_height(), 16);
+
+        let screen = render_frame(&mut ppu, &mut mapper);
+
+        // sprites are drawn one line below their OAM y coordinate
+        for y in 0..240 {
+            let expected = match y {
+                11..=18 => 0x11,
+                19..=26 => 0x12,
+                _ => 0x0f,
+            };
+            assert_eq!(
+                screen.pixels[y][15..25],
+                [
+                    0x0f, expected, expected, expected, expected, expected, expected, expected,
+                    expected, 0x0f
+                ],
+                "line {}",
+                y
+            );
+        }
+
+        // the background is transparent, so there's no sprite zero hit
+        assert_eq!(ppu.status_reg & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_position() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        // every background pixel is opaque, sprite 0 is a solid tile at (16, 10)
+        mapper.chr[0x0000..0x0008].fill(0xff);
+        mapper.chr[0x1010..0x1018].fill(0xff);
+        ppu.oam[..4].copy_from_slice(&[10, 0x01, 0x00, 16]);
+        ppu.control_reg = 0b0000_1000; // sprites from $1000
+        ppu.mask_reg = 0b0001_1110;
+
+        // run up to vblank
+        let mut screen = Screen::default();
+        while ppu.position() != (241, 0) {
+            ppu.step(&mut mapper, &mut screen);
+        }
+        // sprites are drawn one line below their OAM y, dot 0 is idle
+        assert_eq!(ppu.sprite_zero_hit_at(), Some((11, 17)));
+
+        // cleared by the pre-render line
+        render_frame(&mut ppu, &mut mapper);
+        assert_eq!(ppu.sprite_zero_hit_at(), None);
+    }
+
+    #[test]
+    fn test_layer_visibility() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        // every background pixel is color 1, sprite 0 is a solid tile of color 1 at (16, 10)
+        mapper.chr[0x0000..0x0008].fill(0xff);
+        mapper.chr[0x1010..0x1018].fill(0xff);
+        ppu.oam[..4].copy_from_slice(&[10, 0x01, 0x00, 16]);
+        ppu.palette_ram[0x01] = 0x16;
+        ppu.palette_ram[0x11] = 0x2a;
+        ppu.control_reg = 0b0000_1000; // sprites from $1000
+        ppu.mask_reg = 0b0001_1110;
+
+        // up to vblank, before the pre-render line clears the sprite 0 hit
+        let run_frame = |ppu: &mut PPU, mapper: &mut TestMapper| {
+            let mut screen = Screen::default();
+            while ppu.position() != (0, 0) {
+                ppu.step(mapper, &mut screen);
+            }
+            while ppu.position() != (241, 0) {
+                ppu.step(mapper, &mut screen);
+            }
+            screen
+        };
+
+        let screen = run_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[11][16], 0x2a);
+
+        ppu.set_layer_visibility(true, false);
+        let screen = run_frame(&mut ppu, &mut mapper);
+        assert!(screen.pixels.iter().flatten().all(|pixel| *pixel == 0x16));
+        assert_eq!(ppu.sprite_zero_hit_at(), Some((11, 17)));
+
+        ppu.set_layer_visibility(false, true);
+        let screen = run_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[11][16], 0x2a);
+        assert_eq!(screen.pixels[0][0], 0x00);
+        assert_eq!(ppu.sprite_zero_hit_at(), Some((11, 17)));
+    }
+
+    #[test]
+    fn test_preview_scanline() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        // tiles 1-3 are solid colors 1-3, repeating across the third row of the nametable,
+        // and a solid sprite at (100, 20) is drawn from the scanline below
+        mapper.chr[0x0010..0x0018].fill(0xff);
+        mapper.chr[0x0028..0x0030].fill(0xff);
+        mapper.chr[0x0030..0x0040].fill(0xff);
+        for column in 0..32 {
+            ppu.nametables[0x40 + column] = [1, 2, 3, 0][column % 4];
+        }
+        ppu.oam[..4].copy_from_slice(&[20, 0x01, 0x00, 100]);
+        ppu.palette_ram[..4].copy_from_slice(&[0x0f, 0x16, 0x2a, 0x12]);
+        ppu.palette_ram[0x11] = 0x30;
+        ppu.mask_reg = 0b0001_1110;
+        ppu.debug_set_scroll(0, 0, 3);
+
+        let expected: [u8; SCREEN_WIDTH] = core::array::from_fn(|x| match x {
+            100..=107 => 0x30,
+            _ => [0x16, 0x2a, 0x12, 0x0f][(x + 3) / 8 % 4],
+        });
+        let preview = ppu.preview_scanline(&mapper, 21);
+        assert_eq!(preview, expected);
+        assert_eq!(ppu.preview_scanline(&mapper, 20)[100], 0x16);
+        assert_eq!(ppu.preview_scanline(&mapper, 8), [0x0f; SCREEN_WIDTH]);
+
+        // nothing moved, and the mapper didn't see any fetches
+        assert_eq!(ppu.position(), (0, 0));
+        assert_eq!(ppu.status_reg, 0);
+        assert_eq!(mapper.a12_rises, 0);
+
+        // the second frame has v loaded from t on the pre-render line, like the preview
+        render_frame(&mut ppu, &mut mapper);
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert!(screen.pixels[21]
+            .iter()
+            .zip(preview)
+            .all(|(pixel, index)| *pixel == index as u16));
+    }
+
+    #[test]
+    fn test_oamdata_write_while_rendering() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        let mut screen = Screen::default();
+        ppu.set_strict_accuracy(false);
+        ppu.mask_reg = 0b0001_1000; // show background and sprites
+        while ppu.position() != (100, 0) {
+            ppu.step(&mut mapper, &mut screen);
+        }
+
+        ppu.write_register(&mut mapper, 0x2003, 0x01);
+        ppu.write_register(&mut mapper, 0x2004, 0x42);
+        assert_eq!(ppu.oam[0x01], 0x42);
+        assert_eq!(ppu.oam_addr, 0x02);
+
+        // OAM is left alone, and OAMADDR skips to the next sprite
+        ppu.set_strict_accuracy(true);
+        ppu.write_register(&mut mapper, 0x2004, 0x43);
+        assert_eq!(ppu.oam[0x02], 0x00);
+        assert_eq!(ppu.oam_addr, 0x06);
+
+        // outside of rendering, writes go through
+        while ppu.position() != (241, 0) {
+            ppu.step(&mut mapper, &mut screen);
+        }
+        ppu.write_register(&mut mapper, 0x2004, 0x44);
+        assert_eq!(ppu.oam[0x06], 0x44);
+    }
+
+    #[test]
+    fn test_status_open_bus() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.status_reg = 0b1001_1111;
+
+        // OAMADDR is write only, and leaves its value on the bus
+        ppu.write_register(&mut mapper, 0x2003, 0b0110_1010);
+        assert_eq!(ppu.read_register(&mapper, 0x2003), 0b0110_1010);
+        assert_eq!(ppu.read_register(&mapper, 0x2002), 0b1000_1010);
+
+        // the status read is now on the bus
+        assert_eq!(ppu.read_register(&mapper, 0x2005), 0b1000_1010);
+    }
+
+    #[test]
+    fn test_status_read_resets_write_latch() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        // a stray first write leaves the latch set
+        ppu.write_register(&mut mapper, 0x2006, 0x3f);
+
+        // with no step() in between, the writes after the status read still pair up
+        ppu.read_register(&mapper, 0x2002);
+        ppu.write_register(&mut mapper, 0x2006, 0x21);
+        ppu.write_register(&mut mapper, 0x2006, 0x08);
+        assert_eq!(ppu.v, 0x2108);
+
+        ppu.write_register(&mut mapper, 0x2005, 0x08);
+        ppu.read_register(&mapper, 0x2002);
+        ppu.write_register(&mut mapper, 0x2005, 0x10);
+        assert_eq!(ppu.fine_x, 0);
+        assert_eq!(VRAMAddress::from(ppu.t).coarse_x, 2);
+    }
+
+    #[test]
+    fn test_mid_frame_vram_address_write() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        let mut screen = Screen::default();
+        ppu.debug_set_registers(0x00, 0x08);
+
+        let mut step_to = |ppu: &mut PPU, mapper: &mut TestMapper, position: (u16, u16)| {
+            while ppu.position() != position {
+                ppu.step(mapper, &mut screen);
+            }
+        };
+        let address = |ppu: &PPU| {
+            let v = VRAMAddress::from(ppu.v);
+            (v.nametable, v.coarse_y, v.fine_y, v.coarse_x)
+        };
+
+        // halfway through scanline 100, point v at $2345: coarse X 5, coarse Y 26, fine Y 2
+        step_to(&mut ppu, &mut mapper, (100, 100));
+        ppu.write_register(&mut mapper, 0x2006, 0x23);
+        ppu.write_register(&mut mapper, 0x2006, 0x45);
+        assert_eq!(address(&ppu), (0, 26, 2, 5));
+
+        // the next coarse X increment at dot 104 starts from the written address
+        step_to(&mut ppu, &mut mapper, (100, 105));
+        assert_eq!(address(&ppu), (0, 26, 2, 6));
+
+        // fine Y goes down a row at dot 256, and coarse X comes back from t at dot 257
+        step_to(&mut ppu, &mut mapper, (100, 258));
+        assert_eq!(address(&ppu), (0, 26, 3, 5));
+        assert_eq!(ppu.v, 0x3345);
+
+        // and the following scanlines continue from there
+        step_to(&mut ppu, &mut mapper, (105, 258));
+        assert_eq!(address(&ppu), (0, 27, 0, 5));
+    }
+
+    // dots in each of the next `frames` frames, starting from the next frame boundary
+    fn frame_lengths(ppu: &mut PPU, mapper: &mut TestMapper, frames: usize) -> Vec<usize> {
+        let mut screen = Screen::default();
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.step(mapper, &mut screen);
+        }
+
+        (0..frames)
+            .map(|_| {
+                ppu.frame_complete = false;
+                let mut dots = 0;
+                while !ppu.frame_complete {
+                    ppu.step(mapper, &mut screen);
+                    dots += 1;
+                }
+                dots
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_odd_frame_skip() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.mask_reg = 0b0000_1000; // show background
+
+        let mut lengths = frame_lengths(&mut ppu, &mut mapper, 2);
+        lengths.sort();
+        assert_eq!(lengths, vec![341 * 262 - 1, 341 * 262]);
+
+        ppu.set_odd_frame_skip(false);
+        assert_eq!(
+            frame_lengths(&mut ppu, &mut mapper, 2),
+            vec![341 * 262, 341 * 262]
+        );
+    }
+
+    #[test]
+    fn test_keep_debug_settings() {
+        let mut ppu = PPU::default();
+        ppu.set_strict_accuracy(false);
+        ppu.set_odd_frame_skip(false);
+        ppu.set_pal_emphasis(true);
+        ppu.set_vs_palette(true);
+
+        // a PPU restored from before the settings changed takes them on
+        let mut restored = PPU::default();
+        restored.keep_debug_settings(&ppu);
+        assert!(!restored.strict_accuracy);
+        assert!(restored.vs_palette);
+        assert!(restored.pal_emphasis);
+        assert!(!restored.odd_frame_skip);
+    }
+
+    #[test]
+    fn test_odd_frame_skip_sampled_at_dot_339() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        let mut screen = Screen::default();
+
+        // dots until the frame completes, enabling rendering once the PPU reaches `toggle`
+        let mut frame_length = |ppu: &mut PPU, toggle: (u16, u16)| {
+            ppu.mask_reg = 0;
+            ppu.frame_complete = false;
+            let mut dots = 0;
+            while !ppu.frame_complete {
+                if ppu.position() == toggle {
+                    ppu.mask_reg = 0b0000_1000; // show background
+                }
+                ppu.step(&mut mapper, &mut screen);
+                dots += 1;
+            }
+            dots
+        };
+
+        // enabled right before the skip point, frame 1 starts a dot early
+        assert_eq!(frame_length(&mut ppu, (261, 339)), 341 * 262 - 1);
+
+        // frame 2 is even, and isn't shortened
+        assert_eq!(frame_length(&mut ppu, (0, 0)), 341 * 262);
+
+        // enabled at dot 340 is too late
+        assert_eq!(frame_length(&mut ppu, (261, 340)), 341 * 262);
+    }
+
+    #[test]
+    fn test_ppudata_a12() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        let mut screen = Screen::default();
+        let set_address = |ppu: &mut PPU, mapper: &mut TestMapper, addr: u16| {
+            let [lo, hi] = addr.to_le_bytes();
+            ppu.write_register(mapper, 0x2006, hi);
+            ppu.write_register(mapper, 0x2006, lo);
+        };
+
+        set_address(&mut ppu, &mut mapper, 0x0ffe);
+        ppu.write_register(&mut mapper, 0x2007, 0x00);
+        ppu.write_register(&mut mapper, 0x2007, 0x00);
+        assert_eq!(mapper.a12_rises, 0);
+
+        // the increment after a write crosses into $1000
+        ppu.write_register(&mut mapper, 0x2007, 0x00);
+        assert_eq!(mapper.a12_rises, 1);
+        ppu.write_register(&mut mapper, 0x2007, 0x00);
+        assert_eq!(mapper.a12_rises, 1);
+
+        // reads drive the bus when v is incremented on the next cycle
+        set_address(&mut ppu, &mut mapper, 0x0fff);
+        ppu.read_register(&mapper, 0x2007);
+        ppu.step(&mut mapper, &mut screen);
+        ppu.read_register(&mapper, 0x2007);
+        assert_eq!(mapper.a12_rises, 1);
+        ppu.step(&mut mapper, &mut screen);
+        assert_eq!(mapper.a12_rises, 2);
+    }
+
+    #[test]
+    fn test_palette_6_bit() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+
+        ppu.write_register(&mut mapper, 0x2006, 0x3f);
+        ppu.write_register(&mut mapper, 0x2006, 0x01);
+        ppu.write_register(&mut mapper, 0x2007, 0xff);
+        assert_eq!(ppu.read_byte(&mapper, 0x3f01), 0x3f);
+
+        ppu.write_byte(&mut mapper, 0x3f11, 0xc2);
+        assert_eq!(ppu.read_byte(&mapper, 0x3f11), 0x02);
+    }
+
+    #[test]
+    fn test_greyscale_emphasis() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.palette_ram[0x00] = 0x16;
+        // greyscale, show background, emphasize red
+        ppu.mask_reg = 0b0010_1001;
+
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[100][100], 0x10 | EMPHASIS_RED);
+
+        let mut rgb = vec![0u8; 256 * 240 * 3];
+        screen.write_rgb(&mut rgb).unwrap();
+        // $10 is 0xADADAD, with green and blue darkened
+        assert_eq!(&rgb[..3], &[0xad, 0x8d, 0x8d]);
+    }
+
+    #[test]
+    fn test_pal_emphasis() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.palette_ram[0x00] = 0x20;
+        // show background, PPUMASK bit 5
+        ppu.mask_reg = 0b0010_1000;
+
+        let mut rgb = vec![0u8; 256 * 240 * 3];
+        render_frame(&mut ppu, &mut mapper)
+            .write_rgb(&mut rgb)
+            .unwrap();
+        // $20 is 0xFFFEFF, and red emphasis darkens green and blue
+        assert_eq!(&rgb[..3], &[0xff, 0xcf, 0xd0]);
+
+        // on PAL the same bit emphasizes green, darkening red and blue
+        ppu.set_pal_emphasis(true);
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[0][0], 0x20 | EMPHASIS_GREEN);
+        screen.write_rgb(&mut rgb).unwrap();
+        assert_eq!(&rgb[..3], &[0xd0, 0xfe, 0xd0]);
+    }
+
+    #[test]
+    fn test_vs_palette() {
+        let mut mapper = TestMapper::default();
+        let mut ppu = PPU::default();
+        ppu.palette_ram[0x00] = 0x02;
+        ppu.mask_reg = 0b0000_1000; // show background
+
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[0][0], 0x02);
+
+        ppu.set_vs_palette(true);
+        let screen = render_frame(&mut ppu, &mut mapper);
+        assert_eq!(screen.pixels[0][0], 0x16);
+    }
+
+    #[test]
+    fn test_screen_dimensions() {
+        let screen = Screen::default();
+        assert_eq!(screen.pixels.len(), SCREEN_HEIGHT);
+        assert!(screen.pixels.iter().all(|row| row.len() == SCREEN_WIDTH));
+        assert_eq!(PALETTE_RGB.len(), PALETTE_SIZE);
+    }
+
+    #[test]
+    fn test_screen_rows() {
+        let mut screen = Screen::blank(0x0f);
+        screen.pixels[239][255] = 0x21 | EMPHASIS_BLUE;
+
+        assert_eq!(screen.rows().count(), 240);
+        assert!(screen.rows().all(|row| row.len() == 256));
+        assert_eq!(screen.rows().last().unwrap()[255], 0x21 | EMPHASIS_BLUE);
+
+        assert_eq!(screen.index_at(0, 0), 0x0f);
+        assert_eq!(screen.index_at(255, 239), 0x21);
+    }
+
+    #[test]
+    fn test_write_rgb_dimensions() {
+        let mut screen = Screen::default();
+        screen.pixels[0][1] = 0x20;
+
+        let mut small = vec![0u8; 256 * 239 * 3];
+        assert_eq!(
+            screen.write_rgb(&mut small),
+            Err(ScreenError::DimensionMismatch {
+                expected: 256 * 240 * 3,
+                actual: 256 * 239 * 3,
+            })
+        );
+
+        let mut exact = vec![0u8; 256 * 240 * 3];
+        assert_eq!(screen.write_rgb(&mut exact), Ok(()));
+        assert_eq!(&exact[..6], &[0x66, 0x66, 0x66, 0xFF, 0xFE, 0xFF]);
+    }
+
+    #[test]
+    fn test_write_rgb_scaled() {
+        let mut screen = Screen::default();
+        screen.pixels[0][1] = 0x20;
+
+        let mut unscaled = vec![0u8; 256 * 240 * 3];
+        assert_eq!(
+            screen.write_rgb_scaled(&mut unscaled, 2),
+            Err(ScreenError::DimensionMismatch {
+                expected: 512 * 480 * 3,
+                actual: 256 * 240 * 3,
+            })
+        );
+
+        let mut out = vec![0u8; 512 * 480 * 3];
+        assert_eq!(screen.write_rgb_scaled(&mut out, 2), Ok(()));
+        let pixel_at = |x: usize, y: usize| &out[(y * 512 + x) * 3..][..3];
+
+        // pixel (1, 0) covers the 2x2 block at (2, 0)
+        for (x, y) in [(2, 0), (3, 0), (2, 1), (3, 1)] {
+            assert_eq!(pixel_at(x, y), &[0xFF, 0xFE, 0xFF]);
+        }
+        for (x, y) in [(1, 0), (4, 0), (1, 1), (4, 1), (2, 2), (3, 2)] {
+            assert_eq!(pixel_at(x, y), &[0x66, 0x66, 0x66]);
+        }
+        assert_eq!(pixel_at(511, 479), &[0x66, 0x66, 0x66]);
+    }
+}
@@ -1,15 +1,56 @@
-use std::collections::VecDeque;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 
 use crate::{
-    bus::MemoryBus, console::ConsoleState, controller::ButtonState, cpu::CPU, ppu::Screen,
+    bus::MemoryBus,
+    console::{ConsoleState, Timeout},
+    controller::{ButtonState, ZapperState},
+    cpu::CPU,
+    ppu::{Screen, CPU_CYCLES_PER_FRAME},
 };
 
+/// A saved point in emulation, from `Console::take_snapshot`. Opaque to frontends,
+/// it can only be restored into the console it was taken from.
 #[derive(Clone)]
-struct ButtonSequence {
+pub struct Snapshot(pub(crate) ConsoleState);
+
+// The input the frontend held for a frame, all that's needed to replay it
+#[derive(Clone, Copy, PartialEq)]
+struct FrameInput {
     buttons: ButtonState,
+    zapper: ZapperState,
+}
+
+impl FrameInput {
+    fn of(bus: &MemoryBus) -> Self {
+        FrameInput {
+            buttons: bus.button_state,
+            zapper: bus.zapper_state,
+        }
+    }
+
+    fn apply(self, bus: &mut MemoryBus) {
+        bus.update_buttons(self.buttons);
+        bus.update_zapper(self.zapper);
+    }
+}
+
+#[derive(Clone)]
+struct ButtonSequence {
+    buttons: FrameInput,
     count: u8,
 }
 
+// A frame on the tape. Idle frames drop their state, it's replayed from the frame before it.
+type Frame = (Option<ConsoleState>, FrameInput);
+
+// Longest run of idle frames, which is replayed all at once when rewinding into it
+const MAX_IDLE_FRAMES: usize = 60;
+
+// A replayed frame starts from the end of the one before it, so it takes a frame at most.
+// Replays of a state that doesn't reach vblank within the budget give up on it
+const REPLAY_CYCLE_BUDGET: u64 = (CPU_CYCLES_PER_FRAME * 1.5) as u64;
+
 #[derive(Clone)]
 struct Checkpoint {
     base_state: ConsoleState,
@@ -28,12 +69,18 @@ struct Checkpoint {
 ///   |  |   |    |     |      |       |        |         
 ///   |  \....................................... snapshot + RLE buttons
 ///   \-- snapshot + RLE buttons
+///
+/// While the buttons and the screen stay the same (title screens, pauses), the snapshots
+/// are coalesced further: only the buttons are kept, and the state is replayed on rewind.
 pub(crate) struct RewindTape {
     stored_checkpoints: Vec<Checkpoint>,
-    previous_checkpoint: (Vec<(ConsoleState, ButtonState)>, VecDeque<ButtonSequence>),
-    snapshot_cache: Vec<(ConsoleState, ButtonState)>,
+    previous_checkpoint: (Vec<Frame>, VecDeque<ButtonSequence>),
+    snapshot_cache: Vec<Frame>,
     cache_size: usize,
     frames: usize,
+    // None after the console was changed outside of emulation, which can't be replayed
+    last_checksum: Option<u64>,
+    idle_frames: usize,
 }
 
 impl RewindTape {
@@ -44,11 +91,30 @@ impl RewindTape {
             snapshot_cache: Vec::with_capacity(initial_step),
             cache_size: initial_step,
             frames: 0,
+            last_checksum: None,
+            idle_frames: 0,
         }
     }
 
-    /// Push a snapshot onto the tape, compressing full snapshots into the more compressed Checkpoint
-    pub(crate) fn push_back(&mut self, state: ConsoleState) {
+    /// The console was changed outside of emulation, so the next frame can't be replayed
+    /// from the previous one
+    pub(crate) fn mark_modified(&mut self) {
+        self.last_checksum = None;
+    }
+
+    // Full snapshots held by the tape, the bulk of its memory
+    #[cfg(test)]
+    pub(crate) fn stored_states(&self) -> usize {
+        let is_stored = |(state, _): &&Frame| state.is_some();
+
+        self.stored_checkpoints.len()
+            + self.previous_checkpoint.0.iter().filter(is_stored).count()
+            + self.snapshot_cache.iter().filter(is_stored).count()
+    }
+
+    /// Push a snapshot onto the tape, compressing full snapshots into the more compressed Checkpoint.
+    /// `checksum` identifies the screen of the frame, to detect idle frames.
+    pub(crate) fn push_back(&mut self, state: ConsoleState, checksum: u64) {
         // Pack the previous checkpoint first RLE.
         // There are always `cache_size` full snapshots loaded,
         // between the previous, (partially) decoded checkpoing and the pending checkpoint
@@ -65,7 +131,7 @@ impl RewindTape {
         //      |\---- unpacked snapshot
         //      \----- unpacked snapspshot
         //
-        let buttons = state.bus.controller.button_state;
+        let buttons = FrameInput::of(&state.bus);
         let (decoded_snapshots, buttons_rle) = &mut self.previous_checkpoint;
 
         // Move data further "left", first storing a snapshot if one is already fully encoded
@@ -75,17 +141,18 @@ impl RewindTape {
                 let mut stored_rle = buttons_rle.split_off(0);
                 stored_rle.shrink_to_fit();
                 self.stored_checkpoints.push(Checkpoint {
-                    base_state,
+                    base_state: base_state.expect("the first frame of a cache is never idle"),
                     buttons_rle: stored_rle,
                 });
+                #[cfg(feature = "std")]
                 println!(
                     "stored {} frames, {} checkpoints, RLE : cap = {}/len = {}, reserved capacity = {} B, size of checkpoints = {}",
                     self.frames,
                     self.stored_checkpoints.len(),
                     self.stored_checkpoints.last().unwrap().buttons_rle.capacity(),
                     self.stored_checkpoints.last().unwrap().buttons_rle.len(),
-                    self.stored_checkpoints.capacity() * std::mem::size_of::<Checkpoint>(),
-                    std::mem::size_of_val(&self.stored_checkpoints[..]),
+                    self.stored_checkpoints.capacity() * core::mem::size_of::<Checkpoint>(),
+                    core::mem::size_of_val(&self.stored_checkpoints[..]),
                 );
             } else {
                 // convert another expanded snapshot to an RLE button press
@@ -105,13 +172,25 @@ impl RewindTape {
             }
         }
 
+        // A frame is idle when nothing changed since the previous one
+        let idle = self.last_checksum == Some(checksum)
+            && self.idle_frames < MAX_IDLE_FRAMES
+            && matches!(self.snapshot_cache.last(), Some((_, prev_buttons)) if *prev_buttons == buttons);
+        self.last_checksum = Some(checksum);
+
         // Add a frame to the current full snapshot cache, pushing it back to be encoded as RLE when full
         if self.snapshot_cache.len() < self.cache_size {
-            self.snapshot_cache.push((state, buttons));
+            if idle {
+                self.idle_frames += 1;
+                self.snapshot_cache.push((None, buttons));
+            } else {
+                self.idle_frames = 0;
+                self.snapshot_cache.push((Some(state), buttons));
+            }
         } else {
             // The previous checkpoint is empty
             // Avoid wasted allocations by keeping existing allocated buffers intact
-            std::mem::swap(&mut self.previous_checkpoint.0, &mut self.snapshot_cache);
+            core::mem::swap(&mut self.previous_checkpoint.0, &mut self.snapshot_cache);
             self.previous_checkpoint.1.truncate(0);
 
             self.cache_size += 1;
@@ -119,7 +198,8 @@ impl RewindTape {
             // Extend the buffers as necessary and initialize with a single (snapshot, buttons)
             self.snapshot_cache.truncate(0);
             self.snapshot_cache.reserve(self.cache_size);
-            self.snapshot_cache.push((state, buttons));
+            self.snapshot_cache.push((Some(state), buttons));
+            self.idle_frames = 0;
         }
 
         self.frames += 1;
@@ -128,14 +208,16 @@ impl RewindTape {
     /// Pop the most recent Snapshot from the end of the tape, using one NES frame evaluation
     /// to expand out RLE buttons to the next snapshot
     pub(crate) fn pop_back(&mut self, screen: &mut Screen) -> Option<ConsoleState> {
+        replay_idle_frames(&mut self.snapshot_cache, screen);
         let (latest_snapshot, _) = self.snapshot_cache.pop()?;
+        let latest_snapshot = latest_snapshot.expect("idle frames were replayed");
         let (decoded_snapshots, buttons_rle) = &mut self.previous_checkpoint;
 
         // Pull the decoded previous checkpoint into the cache, if the snapshot cache is empty
         if self.snapshot_cache.is_empty() && !decoded_snapshots.is_empty() {
             // The previous checkpoint contains fully decoded snapshots
             // Avoid wasted allocations by keeping existing allocated buffers intact
-            std::mem::swap(decoded_snapshots, &mut self.snapshot_cache);
+            core::mem::swap(decoded_snapshots, &mut self.snapshot_cache);
             buttons_rle.truncate(0);
 
             self.cache_size -= 1;
@@ -146,31 +228,29 @@ impl RewindTape {
 
         // Move data further "right", restoring one when the current checkpoint is fully emptied
         // Decompress RLE and evaluate a frame
+        replay_idle_frames(decoded_snapshots, screen);
         match (decoded_snapshots.last(), buttons_rle.front_mut()) {
-            (Some((prev_state, _)), Some(next_buttons)) => {
+            (Some((Some(prev_state), _)), Some(next_buttons)) => {
                 // convert another expanded snapshot to an RLE button press
                 // pack the buton onto the current sequence, preserving and building RLE
-                let mut next_state = prev_state.clone();
-                next_state
-                    .bus
-                    .controller
-                    .update_buttons(next_buttons.buttons);
-
-                next_state.wait_vblank(screen);
-                decoded_snapshots.push((next_state, next_buttons.buttons));
-
-                if next_buttons.count > 0 {
-                    next_buttons.count -= 1;
-                } else {
+                let next_state = replay_frame(prev_state, next_buttons.buttons, screen);
+                decoded_snapshots.push((Some(next_state), next_buttons.buttons));
+
+                // a sequence holds `count` frames
+                next_buttons.count -= 1;
+                if next_buttons.count == 0 {
                     buttons_rle.pop_front();
                 }
             }
-            _ => {
+            (Some(_), _) => {
+                // all the RLE buttons are expanded, wait for the snapshot cache to drain into them
+            }
+            (None, _) => {
                 if let Some(mut checkpoint) = self.stored_checkpoints.pop() {
-                    let buttons = checkpoint.base_state.bus.controller.button_state;
+                    let buttons = FrameInput::of(&checkpoint.base_state.bus);
                     decoded_snapshots.truncate(0);
-                    std::mem::swap(buttons_rle, &mut checkpoint.buttons_rle);
-                    decoded_snapshots.push((checkpoint.base_state, buttons));
+                    core::mem::swap(buttons_rle, &mut checkpoint.buttons_rle);
+                    decoded_snapshots.push((Some(checkpoint.base_state), buttons));
                 }
             }
         }
@@ -179,3 +259,164 @@ impl RewindTape {
         Some(latest_snapshot)
     }
 }
+
+// Restore the state of the idle frames at the end of `frames`, by replaying them
+// from the last frame that kept its state
+fn replay_idle_frames(frames: &mut [Frame], screen: &mut Screen) {
+    let Some(base) = frames.iter().rposition(|(state, _)| state.is_some()) else {
+        return;
+    };
+
+    for idx in base + 1..frames.len() {
+        let prev_state = frames[idx - 1].0.as_ref().expect("replayed in order");
+        frames[idx].0 = Some(replay_frame(prev_state, frames[idx].1, screen));
+    }
+}
+
+// Run the frame after `prev_state` with `buttons` held. A state that never reaches vblank
+// is handed back as it is, rather than hanging the rewind
+fn replay_frame(
+    prev_state: &ConsoleState,
+    buttons: FrameInput,
+    screen: &mut Screen,
+) -> ConsoleState {
+    let mut next_state = prev_state.clone();
+    buttons.apply(&mut next_state.bus);
+
+    let deadline = next_state.cpu.cycles() + REPLAY_CYCLE_BUDGET;
+    match next_state.wait_vblank_until(screen, None, deadline) {
+        Ok(()) => next_state,
+        Err(Timeout) => {
+            let mut state = prev_state.clone();
+            buttons.apply(&mut state.bus);
+            state
+        }
+    }
+}
+
+// Save states start with a header identifying the format and the board it was saved from:
+// "NESS" magic, format version (u16), mapper number (u8), followed by each component's state.
+// Bump the version whenever the layout of any component changes.
+pub(crate) const SAVE_STATE_MAGIC: [u8; 4] = *b"NESS";
+pub(crate) const SAVE_STATE_VERSION: u16 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    WrongMapper { expected: u8, found: u8 },
+    Truncated,
+    Corrupt,
+}
+
+impl core::fmt::Display for LoadStateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LoadStateError::BadMagic => write!(f, "not a save state"),
+            LoadStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, expected {}",
+                version, SAVE_STATE_VERSION
+            ),
+            LoadStateError::WrongMapper { expected, found } => write!(
+                f,
+                "save state is for mapper {}, but the cartridge uses mapper {}",
+                found, expected
+            ),
+            LoadStateError::Truncated => write!(f, "save state is truncated"),
+            LoadStateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl core::error::Error for LoadStateError {}
+
+/// Little endian encoder for save states
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Little endian decoder for save states, the counterpart to StateWriter
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, LoadStateError> {
+        let mut value = [0u8; 1];
+        self.read_bytes(&mut value)?;
+        Ok(value[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, LoadStateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, LoadStateError> {
+        let mut value = [0u8; 2];
+        self.read_bytes(&mut value)?;
+        Ok(u16::from_le_bytes(value))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, LoadStateError> {
+        let mut value = [0u8; 4];
+        self.read_bytes(&mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, LoadStateError> {
+        let mut value = [0u8; 8];
+        self.read_bytes(&mut value)?;
+        Ok(u64::from_le_bytes(value))
+    }
+
+    pub fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), LoadStateError> {
+        if self.data.len() < out.len() {
+            return Err(LoadStateError::Truncated);
+        }
+
+        let (bytes, rest) = self.data.split_at(out.len());
+        out.copy_from_slice(bytes);
+        self.data = rest;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
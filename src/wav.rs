@@ -0,0 +1,96 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+// http://soundfile.sapp.org/doc/WaveFormat/
+// 16 bit PCM, mono like the APU output
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+const HEADER_SIZE: u32 = 44;
+
+/// Streams audio samples into a `.wav` file. The chunk sizes are only known
+/// at the end, so they're filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            writer,
+            data_size: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.data_size += size_of_val(samples) as u32;
+        Ok(())
+    }
+
+    /// Fill in the chunk sizes, and hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::WavWriter;
+
+    #[test]
+    fn test_header() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        wav.write_samples(&[0, 1, -1]).unwrap();
+        wav.write_samples(&[i16::MAX]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(20), 1); // PCM
+        assert_eq!(u16_at(22), 1); // channels
+        assert_eq!(u32_at(24), 48_000);
+        assert_eq!(u32_at(28), 48_000 * 2);
+        assert_eq!(u16_at(32), 2); // block align
+        assert_eq!(u16_at(34), 16); // bits per sample
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(40), 8);
+        assert_eq!(&data[44..], &[0, 0, 1, 0, 0xff, 0xff, 0xff, 0x7f]);
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "frontend")]
+
+use std::process::Command;
+
+#[test]
+fn test_bench() {
+    // NROM image running `loop: JMP loop`
+    let mut image = b"NES\x1a".to_vec();
+    image.extend_from_slice(&[1, 1, 0]);
+    image.resize(16, 0);
+    image.extend_from_slice(&[0x4c, 0x00, 0x80]);
+    image.resize(16 + 0x3ffc, 0);
+    image.extend_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+    image.resize(16 + 0x4000 + 0x2000, 0);
+
+    let rom = std::env::temp_dir().join("nes-rs-bench.nes");
+    std::fs::write(&rom, image).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nes"))
+        .args(["bench", "--frames", "60", "--rom"])
+        .arg(&rom)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // "60 frames in 0.123s: 487.8 fps, 14527806 cycles/s"
+    let report = String::from_utf8(output.stdout).unwrap();
+    let fps: f64 = report
+        .split(", ")
+        .find_map(|part| part.strip_suffix(" fps"))
+        .and_then(|part| part.rsplit(' ').next())
+        .and_then(|fps| fps.parse().ok())
+        .unwrap_or_else(|| panic!("no fps in {:?}", report));
+    assert!(fps > 1.0, "{}", report);
+}
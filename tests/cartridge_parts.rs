@@ -0,0 +1,39 @@
+use std::rc::Rc;
+
+use nes::cartridge::{self, MirroringMode, CHR};
+use nes::console::{CartridgeInfo, Console};
+use nes::cpu::ExecutedInstruction;
+
+#[test]
+fn test_from_parts() {
+    // reset: LDA #$42; STA $6000; JMP $C005
+    let mut prg = [0u8; 0x4000];
+    prg[0x1000..0x1008].copy_from_slice(&[0xa9, 0x42, 0x8d, 0x00, 0x60, 0x4c, 0x05, 0xd0]);
+    prg[0x3ffc..0x3ffe].copy_from_slice(&[0x00, 0xd0]);
+
+    let cartridge = cartridge::from_parts(
+        vec![prg],
+        CHR::RAM(Rc::new(vec![[0u8; 0x2000]])),
+        MirroringMode::Vertical,
+    );
+    let mut console = Console::new(cartridge::new(cartridge, 0).unwrap());
+
+    assert_eq!(
+        console.cartridge_info(),
+        CartridgeInfo {
+            mapper: 0,
+            prg_kb: 16,
+            chr_kb: 8,
+            chr_ram: true,
+        }
+    );
+    assert!(!console.has_battery_ram());
+
+    // the bank is mirrored at $C000, where the reset vector points
+    assert!(matches!(
+        console.step_instruction(),
+        ExecutedInstruction::Instruction { pc: 0xd000, .. }
+    ));
+    console.run_frames(1).unwrap();
+    assert_eq!(console.peek(0x6000), 0x42);
+}
@@ -0,0 +1,69 @@
+use nes::cartridge::{Cartridge, Mapper, MirroringMode};
+use nes::{console::Console, ines};
+
+// a board outside of the crate: 16 KB of PRG ROM mirrored across $8000-$FFFF,
+// with a register at $6000 counting the writes to ROM
+#[derive(Clone)]
+struct WriteCounter {
+    cartridge: Cartridge,
+    writes: u8,
+}
+
+// only the required methods, everything else is left to the defaults
+impl Mapper for WriteCounter {
+    fn mirror(&self) -> MirroringMode {
+        self.cartridge.mirror
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1fff => self.cartridge.chr.get_banks()[0][address as usize],
+            0x6000 => self.writes,
+            0x8000.. => self.cartridge.prg.banks()[0][address as usize % 0x4000],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, _data: u8) {
+        if address >= 0x8000 {
+            self.writes = self.writes.wrapping_add(1);
+        }
+    }
+
+    fn read_page(&self, _page: u8) -> Option<&[u8; 256]> {
+        None
+    }
+}
+
+#[test]
+fn test_custom_mapper() {
+    let mut image = b"NES\x1a".to_vec();
+    image.extend_from_slice(&[1, 1, 0]);
+    image.resize(16, 0);
+
+    // loop: STA $8000; JMP loop
+    let mut prg = [0u8; 0x4000];
+    prg[..6].copy_from_slice(&[0x8d, 0x00, 0x80, 0x4c, 0x00, 0x80]);
+    prg[0x3ffa..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    image.extend_from_slice(&prg);
+    image.resize(16 + 0x4000 + 0x2000, 0);
+
+    let (cartridge, _) = ines::load_bytes(&image).unwrap();
+    let mut console = Console::new(Box::new(WriteCounter {
+        cartridge,
+        writes: 0,
+    }));
+
+    assert_eq!(console.cartridge_info().mapper, 0xff);
+    assert_eq!(console.peek(0x6000), 0);
+    console.step_instruction();
+    assert_eq!(console.peek(0x6000), 1);
+
+    // JMP, then STA again
+    console.step_instruction();
+    console.step_instruction();
+    assert_eq!(console.peek(0x6000), 2);
+
+    console.run_frames(1).unwrap();
+    assert!(console.peek(0x6000) > 2);
+}
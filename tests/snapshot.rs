@@ -0,0 +1,56 @@
+use nes::{cartridge, console::Console, ines};
+
+// NROM image running `loop: INC $10; JMP loop`
+fn counter_console() -> Console {
+    let mut image = b"NES\x1a".to_vec();
+    image.extend_from_slice(&[1, 1, 0]);
+    image.resize(16, 0);
+
+    let mut prg = [0u8; 0x4000];
+    prg[..5].copy_from_slice(&[0xe6, 0x10, 0x4c, 0x00, 0x80]);
+    prg[0x3ffa..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    image.extend_from_slice(&prg);
+    image.resize(16 + 0x4000 + 0x2000, 0);
+
+    let (cartridge, mapper) = ines::load_bytes(&image).unwrap();
+    Console::new(cartridge::new(cartridge, mapper).unwrap())
+}
+
+#[test]
+fn test_snapshot_step_restore() {
+    let mut console = counter_console();
+    console.wait_vblank();
+
+    let snapshot = console.take_snapshot();
+    let saved = console.save_state();
+    let counter = console.peek(0x0010);
+
+    // taking a snapshot doesn't advance emulation
+    assert_eq!(console.save_state(), saved);
+
+    console.next_screen();
+    console.step_instruction();
+    assert_ne!(console.peek(0x0010), counter);
+
+    console.restore_snapshot(snapshot);
+    assert_eq!(console.peek(0x0010), counter);
+    assert_eq!(console.save_state(), saved);
+}
+
+#[test]
+fn test_snapshot_restore_twice() {
+    let mut console = counter_console();
+    console.wait_vblank();
+
+    let snapshot = console.take_snapshot();
+    let counter = console.peek(0x0010);
+
+    // a snapshot is a handle that can be cloned and restored any number of times
+    for _ in 0..2 {
+        console.run_frames(2).unwrap();
+        assert_ne!(console.peek(0x0010), counter);
+
+        console.restore_snapshot(snapshot.clone());
+        assert_eq!(console.peek(0x0010), counter);
+    }
+}